@@ -1,64 +1,518 @@
-use criterion::{Criterion, criterion_group, criterion_main};
-use learning_impl::trie::radix::RadixTrie;
+use criterion::{BatchSize, BenchmarkGroup, Criterion, criterion_group, measurement::WallTime};
+use learning_impl::trie::{
+    critbit::CritBitTree, double_array::DoubleArrayTrie, naive::NaiveTrie, radix::RadixTrie,
+};
 use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    collections::{BTreeSet, HashSet},
     fs::File,
     hint::black_box,
     io::{BufRead, BufReader},
+    ops::Bound,
+    sync::atomic::{AtomicUsize, Ordering},
 };
 
-pub fn bench_insert(c: &mut Criterion) {
-    c.bench_function("bench insertion", |b| {
-        let words = load_words();
-        b.iter(move || {
-            let mut trie = RadixTrie::new();
-            for word in words.clone() {
-                trie.insert(black_box(&word));
-            }
-        })
-    });
+// Keeps track of the bytes currently allocated on the heap, so we can see how much memory a trie holds on to.
+struct CountingAllocator;
+
+static ALLOCATED: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATED.fetch_add(layout.size(), Ordering::Relaxed);
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        ALLOCATED.fetch_sub(layout.size(), Ordering::Relaxed);
+        unsafe { System.dealloc(ptr, layout) }
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATED.fetch_add(new_size, Ordering::Relaxed);
+        ALLOCATED.fetch_sub(layout.size(), Ordering::Relaxed);
+        unsafe { System.realloc(ptr, layout, new_size) }
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
+
+// The shared harness: every implementation is benchmarked through these traits, so each workload runs the exact
+// same operations against all of them.
+
+/// A set of words that can be built and queried.
+trait WordSet: Sized {
+    const NAME: &'static str;
+
+    /// Builds the set from the word list, which is sorted.
+    fn build(words: &[String]) -> Self;
+
+    fn contains(&self, word: &str) -> bool;
+}
+
+/// A set of words that can also be changed after it's built.
+trait MutableWordSet: WordSet {
+    fn insert(&mut self, word: &str);
+
+    fn remove(&mut self, word: &str) -> bool;
+}
+
+/// A set of words that can list the words starting with a prefix.
+trait PrefixWordSet: WordSet {
+    fn count_prefix(&self, prefix: &str) -> usize;
+}
+
+impl WordSet for NaiveTrie {
+    const NAME: &'static str = "naive";
+
+    fn build(words: &[String]) -> Self {
+        let mut trie = NaiveTrie::new();
+        for word in words {
+            trie.insert(word);
+        }
+        trie
+    }
+
+    fn contains(&self, word: &str) -> bool {
+        self.search(word)
+    }
 }
 
-pub fn bench_search(c: &mut Criterion) {
-    c.bench_function("bench search", |b| {
-        let words = load_words();
+impl MutableWordSet for NaiveTrie {
+    fn insert(&mut self, word: &str) {
+        NaiveTrie::insert(self, word);
+    }
+
+    fn remove(&mut self, word: &str) -> bool {
+        self.delete(word)
+    }
+}
+
+impl WordSet for RadixTrie {
+    const NAME: &'static str = "radix";
+
+    fn build(words: &[String]) -> Self {
         let mut trie = RadixTrie::new();
-        for word in words.clone() {
-            trie.insert(black_box(&word));
+        for word in words {
+            trie.insert(word);
         }
-        b.iter(move || {
-            for word in words.clone() {
-                let found = trie.search(black_box(&word));
-                assert!(found);
+        trie
+    }
+
+    fn contains(&self, word: &str) -> bool {
+        self.search(word)
+    }
+}
+
+impl MutableWordSet for RadixTrie {
+    fn insert(&mut self, word: &str) {
+        RadixTrie::insert(self, word);
+    }
+
+    fn remove(&mut self, word: &str) -> bool {
+        self.delete(word)
+    }
+}
+
+impl PrefixWordSet for RadixTrie {
+    fn count_prefix(&self, prefix: &str) -> usize {
+        self.words_with_prefix(prefix).len()
+    }
+}
+
+impl WordSet for CritBitTree {
+    const NAME: &'static str = "critbit";
+
+    fn build(words: &[String]) -> Self {
+        let mut tree = CritBitTree::new();
+        for word in words {
+            tree.insert(word);
+        }
+        tree
+    }
+
+    fn contains(&self, word: &str) -> bool {
+        CritBitTree::contains(self, word)
+    }
+}
+
+impl MutableWordSet for CritBitTree {
+    fn insert(&mut self, word: &str) {
+        CritBitTree::insert(self, word);
+    }
+
+    fn remove(&mut self, word: &str) -> bool {
+        self.delete(word)
+    }
+}
+
+impl PrefixWordSet for CritBitTree {
+    fn count_prefix(&self, prefix: &str) -> usize {
+        self.iter_prefix(prefix).count()
+    }
+}
+
+// The double-array trie is static, so it only takes part in the read-only workloads.
+impl WordSet for DoubleArrayTrie {
+    const NAME: &'static str = "double-array";
+
+    fn build(words: &[String]) -> Self {
+        DoubleArrayTrie::from_sorted_words(words)
+    }
+
+    fn contains(&self, word: &str) -> bool {
+        DoubleArrayTrie::contains(self, word)
+    }
+}
+
+impl WordSet for BTreeSet<String> {
+    const NAME: &'static str = "BTreeSet";
+
+    fn build(words: &[String]) -> Self {
+        words.iter().cloned().collect()
+    }
+
+    fn contains(&self, word: &str) -> bool {
+        BTreeSet::contains(self, word)
+    }
+}
+
+impl MutableWordSet for BTreeSet<String> {
+    fn insert(&mut self, word: &str) {
+        BTreeSet::insert(self, word.to_string());
+    }
+
+    fn remove(&mut self, word: &str) -> bool {
+        BTreeSet::remove(self, word)
+    }
+}
+
+impl PrefixWordSet for BTreeSet<String> {
+    fn count_prefix(&self, prefix: &str) -> usize {
+        self.range::<str, _>((Bound::Included(prefix), Bound::Unbounded))
+            .take_while(|word| word.starts_with(prefix))
+            .count()
+    }
+}
+
+impl WordSet for HashSet<String> {
+    const NAME: &'static str = "HashSet";
+
+    fn build(words: &[String]) -> Self {
+        words.iter().cloned().collect()
+    }
+
+    fn contains(&self, word: &str) -> bool {
+        HashSet::contains(self, word)
+    }
+}
+
+impl MutableWordSet for HashSet<String> {
+    fn insert(&mut self, word: &str) {
+        HashSet::insert(self, word.to_string());
+    }
+
+    fn remove(&mut self, word: &str) -> bool {
+        HashSet::remove(self, word)
+    }
+}
+
+// Without any order, a prefix scan has to look at every word.
+impl PrefixWordSet for HashSet<String> {
+    fn count_prefix(&self, prefix: &str) -> usize {
+        self.iter().filter(|word| word.starts_with(prefix)).count()
+    }
+}
+
+// The workloads. Inputs are prepared once, outside of the measured closures.
+
+fn bench_build<S: WordSet>(group: &mut BenchmarkGroup<WallTime>, words: &[String]) {
+    group.bench_function(S::NAME, |b| b.iter(|| S::build(black_box(words))));
+}
+
+fn bench_lookup<S: WordSet>(
+    group: &mut BenchmarkGroup<WallTime>,
+    words: &[String],
+    queries: &[Query],
+) {
+    let set = S::build(words);
+    group.bench_function(S::NAME, |b| {
+        b.iter(|| {
+            for query in queries {
+                assert_eq!(set.contains(black_box(&query.word)), query.is_hit);
             }
         })
     });
 }
 
-pub fn bench_delete(c: &mut Criterion) {
-    c.bench_function("bench delete", |b| {
-        let words = load_words();
-        let mut trie = RadixTrie::new();
-        for word in words.clone() {
-            trie.insert(black_box(&word));
-        }
-        b.iter(move || {
-            for word in words.clone() {
-                trie.delete(black_box(&word));
+// Every iteration deletes from a freshly built set, so that each delete actually removes a word.
+fn bench_delete<S: MutableWordSet>(group: &mut BenchmarkGroup<WallTime>, words: &[String]) {
+    group.bench_function(S::NAME, |b| {
+        b.iter_batched(
+            || S::build(words),
+            |mut set| {
+                for word in words {
+                    assert!(set.remove(black_box(word)));
+                }
+            },
+            BatchSize::LargeInput,
+        )
+    });
+}
+
+fn bench_mixed<S: MutableWordSet>(
+    group: &mut BenchmarkGroup<WallTime>,
+    initial: &[String],
+    ops: &[Op],
+) {
+    group.bench_function(S::NAME, |b| {
+        b.iter_batched(
+            || S::build(initial),
+            |mut set| {
+                for op in ops {
+                    match op {
+                        Op::Read(word) => {
+                            black_box(set.contains(black_box(word)));
+                        }
+                        Op::Insert(word) => set.insert(black_box(word)),
+                        Op::Remove(word) => {
+                            black_box(set.remove(black_box(word)));
+                        }
+                    }
+                }
+            },
+            BatchSize::LargeInput,
+        )
+    });
+}
+
+fn bench_prefix_scan<S: PrefixWordSet>(
+    group: &mut BenchmarkGroup<WallTime>,
+    words: &[String],
+    prefixes: &[(String, usize)],
+) {
+    let set = S::build(words);
+    group.bench_function(S::NAME, |b| {
+        b.iter(|| {
+            for (prefix, expected) in prefixes {
+                assert_eq!(set.count_prefix(black_box(prefix)), *expected);
             }
         })
     });
 }
 
+fn report_memory<S: WordSet>(words: &[String]) {
+    let word_bytes: usize = words.iter().map(|word| word.len()).sum();
+
+    let allocated_before = ALLOCATED.load(Ordering::Relaxed);
+    let set = S::build(words);
+    let set_bytes = ALLOCATED.load(Ordering::Relaxed) - allocated_before;
+
+    println!(
+        "{:>12} memory: {set_bytes:>10} bytes for {} keys ({word_bytes} bytes of word data), {:.1} bytes per key",
+        S::NAME,
+        words.len(),
+        set_bytes as f64 / words.len() as f64
+    );
+    drop(set);
+}
+
+pub fn bench_builds(c: &mut Criterion) {
+    let words = load_words();
+    let mut group = c.benchmark_group("build");
+    bench_build::<NaiveTrie>(&mut group, &words);
+    bench_build::<RadixTrie>(&mut group, &words);
+    bench_build::<CritBitTree>(&mut group, &words);
+    bench_build::<DoubleArrayTrie>(&mut group, &words);
+    bench_build::<BTreeSet<String>>(&mut group, &words);
+    bench_build::<HashSet<String>>(&mut group, &words);
+    group.finish();
+}
+
+pub fn bench_lookups(c: &mut Criterion) {
+    let words = load_words();
+    let workloads = [
+        ("lookup hits", hit_queries(&words)),
+        ("lookup miss-heavy", miss_heavy_queries(&words)),
+    ];
+    for (name, queries) in workloads {
+        let mut group = c.benchmark_group(name);
+        bench_lookup::<NaiveTrie>(&mut group, &words, &queries);
+        bench_lookup::<RadixTrie>(&mut group, &words, &queries);
+        bench_lookup::<CritBitTree>(&mut group, &words, &queries);
+        bench_lookup::<DoubleArrayTrie>(&mut group, &words, &queries);
+        bench_lookup::<BTreeSet<String>>(&mut group, &words, &queries);
+        bench_lookup::<HashSet<String>>(&mut group, &words, &queries);
+        group.finish();
+    }
+}
+
+pub fn bench_deletes(c: &mut Criterion) {
+    let words = load_words();
+    let mut group = c.benchmark_group("delete");
+    bench_delete::<NaiveTrie>(&mut group, &words);
+    bench_delete::<RadixTrie>(&mut group, &words);
+    bench_delete::<CritBitTree>(&mut group, &words);
+    bench_delete::<BTreeSet<String>>(&mut group, &words);
+    bench_delete::<HashSet<String>>(&mut group, &words);
+    group.finish();
+}
+
+pub fn bench_mixed_workloads(c: &mut Criterion) {
+    let words = load_words();
+    // Start from every other word, the writes then insert the missing ones and remove existing ones.
+    let initial: Vec<String> = words.iter().step_by(2).cloned().collect();
+    for read_percent in [90, 50] {
+        let ops = mixed_ops(&words, read_percent, 100_000);
+        let mut group = c.benchmark_group(format!("mixed {read_percent}% reads"));
+        bench_mixed::<NaiveTrie>(&mut group, &initial, &ops);
+        bench_mixed::<RadixTrie>(&mut group, &initial, &ops);
+        bench_mixed::<CritBitTree>(&mut group, &initial, &ops);
+        bench_mixed::<BTreeSet<String>>(&mut group, &initial, &ops);
+        bench_mixed::<HashSet<String>>(&mut group, &initial, &ops);
+        group.finish();
+    }
+}
+
+pub fn bench_prefix_scans(c: &mut Criterion) {
+    let words = load_words();
+    let prefixes = scan_prefixes(&words);
+    let mut group = c.benchmark_group("prefix scan");
+    bench_prefix_scan::<RadixTrie>(&mut group, &words, &prefixes);
+    bench_prefix_scan::<CritBitTree>(&mut group, &words, &prefixes);
+    bench_prefix_scan::<BTreeSet<String>>(&mut group, &words, &prefixes);
+    bench_prefix_scan::<HashSet<String>>(&mut group, &words, &prefixes);
+    group.finish();
+}
+
+pub fn report_memory_usage() {
+    let words = load_words();
+    report_memory::<NaiveTrie>(&words);
+    report_memory::<RadixTrie>(&words);
+    report_memory::<CritBitTree>(&words);
+    report_memory::<DoubleArrayTrie>(&words);
+    report_memory::<BTreeSet<String>>(&words);
+    report_memory::<HashSet<String>>(&words);
+}
+
+// Workload generation.
+
+struct Query {
+    word: String,
+    is_hit: bool,
+}
+
+enum Op {
+    Read(String),
+    Insert(String),
+    Remove(String),
+}
+
+// A small xorshift generator, so the workloads are random but the same on every run.
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+}
+
+fn hit_queries(words: &[String]) -> Vec<Query> {
+    words
+        .iter()
+        .map(|word| Query {
+            word: word.clone(),
+            is_hit: true,
+        })
+        .collect()
+}
+
+// Nine misses for every hit. Half of the misses share the whole word with a key and only fail at the end, the
+// other half are reversed words which usually fail early.
+fn miss_heavy_queries(words: &[String]) -> Vec<Query> {
+    let present: HashSet<&str> = words.iter().map(String::as_str).collect();
+    let mut rng = Rng(0x2545_f491_4f6c_dd1d);
+    (0..words.len())
+        .map(|i| {
+            let word = &words[rng.below(words.len())];
+            let query = match i % 10 {
+                0 => word.clone(),
+                n if n % 2 == 1 => format!("{word}#"),
+                _ => word.chars().rev().collect(),
+            };
+            let is_hit = present.contains(query.as_str());
+            Query {
+                word: query,
+                is_hit,
+            }
+        })
+        .collect()
+}
+
+fn mixed_ops(words: &[String], read_percent: usize, count: usize) -> Vec<Op> {
+    let mut rng = Rng(0x9e37_79b9_7f4a_7c15);
+    (0..count)
+        .map(|_| {
+            let word = words[rng.below(words.len())].clone();
+            if rng.below(100) < read_percent {
+                Op::Read(word)
+            } else if rng.below(2) == 0 {
+                Op::Insert(word)
+            } else {
+                Op::Remove(word)
+            }
+        })
+        .collect()
+}
+
+// Prefixes of a few lengths, taken from words spread over the list, with the number of words they match.
+fn scan_prefixes(words: &[String]) -> Vec<(String, usize)> {
+    let sorted: BTreeSet<String> = words.iter().cloned().collect();
+    words
+        .iter()
+        .step_by(997)
+        .enumerate()
+        .map(|(i, word)| {
+            let prefix: String = word.chars().take(2 + i % 4).collect();
+            let count = sorted.count_prefix(&prefix);
+            (prefix, count)
+        })
+        .collect()
+}
+
 // Word list comes from the crate https://crates.io/crates/random_word.
 fn load_words() -> Vec<String> {
     let file = File::open("benches/radix_benchmark/bench_data.txt")
         .expect("Unable to open the word list file");
     let reader = BufReader::new(file);
-    reader
+    let mut words: Vec<String> = reader
         .lines()
         .map(|line| line.expect("Error reading line"))
-        .collect()
+        .collect();
+    words.sort();
+    words.dedup();
+    words
 }
 
-criterion_group!(benches, bench_insert, bench_delete, bench_search);
-criterion_main!(benches);
+criterion_group!(
+    benches,
+    bench_builds,
+    bench_lookups,
+    bench_deletes,
+    bench_mixed_workloads,
+    bench_prefix_scans
+);
+
+fn main() {
+    report_memory_usage();
+    benches();
+    Criterion::default().configure_from_args().final_summary();
+}
@@ -6,7 +6,7 @@ fn main() {
     extension!(
         runjs,
         esm_entry_point = "ext:runjs/runtime.js",
-        esm = [dir "src/runjs/jscore", "runtime.js"]
+        esm = [dir "src/runjs/jscore", "console.js", "testing.js", "runtime.js"]
     );
 
     let out_dir = PathBuf::from(env::var_os("OUT_DIR").unwrap());
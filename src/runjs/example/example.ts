@@ -1,3 +1,5 @@
+// Run with: cargo run --bin runjs -- --allow-read --allow-write --allow-net src/runjs/example/example.ts
+
 console.log("Hello", "runjs!");
 console.error("Boom!");
 
@@ -21,7 +23,10 @@ console.log("Removing file", path);
 runjs.removeFile(path);
 console.log("File removed");
 
-let content: string = await runjs.fetch(
+const response = await fetch(
     "https://deno.land/std@0.177.0/examples/welcome.ts",
 );
+console.log("Fetched", response.url, "with status", response.status);
+console.log("Content type", response.headers.get("content-type"));
+const content: string = await response.text();
 console.log("Content from fetch", content);
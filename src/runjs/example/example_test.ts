@@ -0,0 +1,24 @@
+// Run with: cargo run --bin runjs -- test src/runjs/example
+
+const { test, assert, assertEquals, assertRejects } = runjs;
+
+test("adds numbers", () => {
+    assertEquals(1 + 2, 3);
+});
+
+test("compares objects structurally", () => {
+    assertEquals({ name: "runjs", tags: ["js", "ts"] }, { name: "runjs", tags: ["js", "ts"] });
+});
+
+test("waits for async tests", async () => {
+    const value = await new Promise((resolve) => setTimeout(() => resolve("done"), 10));
+    assert(value === "done");
+});
+
+test("checks rejections", async () => {
+    await assertRejects(() => runjs.readFile("missing.txt"), runjs.errors.PermissionDenied);
+});
+
+test("runs with a timeout", async () => {
+    await new Promise((resolve) => setTimeout(resolve, 10));
+}, { timeout: 1000 });
@@ -0,0 +1,248 @@
+// The op behind the global `fetch`. The JS side in `jscore/runtime.js` builds the `Request` and `Response` objects,
+// this only sends the request with reqwest and hands back the status, headers and body.
+
+use crate::RuntimeError;
+use crate::permissions::{PermissionDenied, SharedPermissions};
+use deno_core::JsBuffer;
+use deno_core::OpState;
+use deno_core::ToJsBuffer;
+use deno_core::op2;
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::rc::Rc;
+
+#[derive(Debug, Deserialize)]
+pub struct FetchRequest {
+    url: String,
+    method: String,
+    headers: Vec<(String, String)>,
+    body: Option<JsBuffer>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FetchResponse {
+    // The URL the response came from, after following redirects.
+    url: String,
+    status: u16,
+    status_text: String,
+    headers: Vec<(String, String)>,
+    body: ToJsBuffer,
+}
+
+// The same limit reqwest follows by default.
+const MAX_REDIRECTS: usize = 10;
+
+/// Builds the client `op_fetch` sends requests with. Redirects are checked against the net permissions like the
+/// first URL, so a script can't reach a host it wasn't allowed to by being redirected there.
+pub fn client(permissions: SharedPermissions) -> reqwest::Client {
+    let policy = reqwest::redirect::Policy::custom(move |attempt| {
+        if attempt.previous().len() > MAX_REDIRECTS {
+            return attempt.error("too many redirects");
+        }
+        match permissions.lock().unwrap().check_net(attempt.url()) {
+            Ok(()) => attempt.follow(),
+            Err(err) => attempt.error(err),
+        }
+    });
+    reqwest::Client::builder()
+        .redirect(policy)
+        .build()
+        .expect("the HTTP client has a valid configuration")
+}
+
+/// The permission error a request sent with `client` failed with, when it was redirected to a host that isn't allowed.
+/// That fails like requesting the host directly.
+pub fn permission_denied(error: &reqwest::Error) -> Option<&PermissionDenied> {
+    std::iter::successors(std::error::Error::source(error), |e| e.source())
+        .find_map(|e| e.downcast_ref::<PermissionDenied>())
+}
+
+#[op2(async)]
+#[serde]
+pub async fn op_fetch(
+    state: Rc<RefCell<OpState>>,
+    #[serde] request: FetchRequest,
+) -> Result<FetchResponse, RuntimeError> {
+    let url = reqwest::Url::parse(&request.url).map_err(|e| RuntimeError::Http(e.to_string()))?;
+    let method = reqwest::Method::from_bytes(request.method.as_bytes())
+        .map_err(|e| RuntimeError::Http(e.to_string()))?;
+
+    let client = {
+        let state = state.borrow();
+        state
+            .borrow::<SharedPermissions>()
+            .lock()
+            .unwrap()
+            .check_net(&url)
+            .map_err(RuntimeError::Permission)?;
+        state.borrow::<reqwest::Client>().clone()
+    };
+
+    let mut builder = client.request(method, url);
+    for (name, value) in request.headers {
+        builder = builder.header(name, value);
+    }
+    if let Some(body) = request.body {
+        builder = builder.body(body.to_vec());
+    }
+
+    let response = builder
+        .send()
+        .await
+        .map_err(|e| match permission_denied(&e) {
+            Some(denied) => RuntimeError::Permission(denied.clone()),
+            None => RuntimeError::Http(e.to_string()),
+        })?;
+    let url = response.url().to_string();
+    let status = response.status();
+    let headers = response
+        .headers()
+        .iter()
+        .map(|(name, value)| {
+            let value = String::from_utf8_lossy(value.as_bytes()).into_owned();
+            (name.to_string(), value)
+        })
+        .collect();
+    let body = response
+        .bytes()
+        .await
+        .map_err(|e| RuntimeError::Http(e.to_string()))?;
+
+    Ok(FetchResponse {
+        url,
+        status: status.as_u16(),
+        status_text: status.canonical_reason().unwrap_or_default().to_string(),
+        headers,
+        body: body.to_vec().into(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::permissions::Permissions;
+    use crate::test_util::{self, ASSERT, run_script};
+
+    // `/echo` answers with the method, the `x-test` header and the body of the request, `/json` answers with a fixed
+    // JSON document and anything else is a 404.
+    fn serve() -> u16 {
+        test_util::serve(|request| match request.path.as_str() {
+            "/echo" => (
+                "201 Created",
+                "text/plain",
+                format!(
+                    "{} {} {}",
+                    request.method,
+                    request.header("x-test").unwrap_or_default(),
+                    String::from_utf8_lossy(&request.body)
+                ),
+            ),
+            "/json" => (
+                "200 OK",
+                "application/json",
+                r#"{"name":"runjs","tags":["js","ts"]}"#.to_string(),
+            ),
+            _ => ("404 Not Found", "text/plain", "not found".to_string()),
+        })
+    }
+
+    fn allow_net() -> Permissions {
+        Permissions::from_args(vec!["--allow-net".to_string()]).0
+    }
+
+    #[tokio::test]
+    async fn test_fetch_response() {
+        let port = serve();
+        let source = format!(
+            r#"{ASSERT}
+            const response = await fetch("http://127.0.0.1:{port}/json");
+            assertEquals(response.status, 200);
+            assertEquals(response.ok, true);
+            assertEquals(response.statusText, "OK");
+            assertEquals(response.headers.get("Content-Type"), "application/json");
+            assertEquals(response.headers.get("x-server"), "stand-in");
+            assertEquals(await response.json(), {{ name: "runjs", tags: ["js", "ts"] }});
+            assertEquals(response.bodyUsed, true);
+
+            const missing = await fetch("http://127.0.0.1:{port}/missing");
+            assertEquals(missing.status, 404);
+            assertEquals(missing.ok, false);
+            const bytes = new Uint8Array(await missing.arrayBuffer());
+            assertEquals(bytes.length, "not found".length);
+            "#
+        );
+
+        run_script("fetch_response.js", &source, allow_net())
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_fetch_method_headers_and_body() {
+        let port = serve();
+        let source = format!(
+            r#"{ASSERT}
+            const response = await fetch("http://127.0.0.1:{port}/echo", {{
+                method: "PUT",
+                headers: {{ "X-Test": "hello" }},
+                body: "some body",
+            }});
+            assertEquals(response.status, 201);
+            assertEquals(await response.text(), "PUT hello some body");
+
+            const request = new Request("http://127.0.0.1:{port}/echo", {{
+                method: "POST",
+                headers: new Headers([["x-test", "bytes"]]),
+                body: new Uint8Array([104, 105]),
+            }});
+            assertEquals(await (await fetch(request)).text(), "POST bytes hi");
+            "#
+        );
+
+        run_script("fetch_request.js", &source, allow_net())
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_fetch_checks_redirects() {
+        let port = serve();
+        let allowed = test_util::serve_redirect(format!("http://127.0.0.1:{port}/json"));
+        let denied = test_util::serve_redirect("http://localhost:1/json".to_string());
+        let source = format!(
+            r#"{ASSERT}
+            const response = await fetch("http://127.0.0.1:{allowed}/");
+            assertEquals(response.url, "http://127.0.0.1:{port}/json");
+            assertEquals(await response.json(), {{ name: "runjs", tags: ["js", "ts"] }});
+
+            try {{
+                await fetch("http://127.0.0.1:{denied}/");
+                throw new Error("the redirect should have been denied");
+            }} catch (error) {{
+                assertEquals(error instanceof runjs.errors.PermissionDenied, true);
+                assertEquals(error.message.startsWith("Requires net access to localhost:1"), true);
+            }}
+            "#
+        );
+        let (permissions, _) = Permissions::from_args(vec![format!(
+            "--allow-net=127.0.0.1:{port},127.0.0.1:{allowed},127.0.0.1:{denied}"
+        )]);
+
+        run_script("fetch_redirects.js", &source, permissions)
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_fetch_requires_net_permission() {
+        let port = serve();
+        let source = format!(r#"await fetch("http://127.0.0.1:{port}/json");"#);
+        let result = run_script("fetch_denied.js", &source, Permissions::default()).await;
+
+        let error = result.unwrap_err().to_string();
+        assert!(
+            error.contains("Requires net access to 127.0.0.1"),
+            "{error}"
+        );
+    }
+}
@@ -0,0 +1,367 @@
+// Import maps, following https://html.spec.whatwg.org/multipage/webappapis.html#import-maps.
+//
+// An import map rewrites the specifiers of imports, usually to give bare specifiers like `lodash` a URL. Keys ending
+// with a slash map every specifier starting with them, and scopes apply their own mappings to the modules under a
+// URL prefix, before the top-level `imports`. The map comes from the file passed with `--import-map`, or else from
+// `runjs.json` in the current directory, which either points at an import map with `importMap` or holds `imports`
+// and `scopes` itself.
+
+use deno_core::ModuleSpecifier;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+pub const CONFIG_FILE: &str = "runjs.json";
+
+#[derive(Debug, Error)]
+pub enum ImportMapError {
+    #[error("Invalid import map {path}: {reason}")]
+    Invalid { path: String, reason: String },
+
+    #[error("Import of {specifier} is blocked by the import map: {reason}")]
+    Blocked {
+        specifier: String,
+        reason: &'static str,
+    },
+
+    #[error("Failed to read {path}: {source}")]
+    Io {
+        path: String,
+        source: std::io::Error,
+    },
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct ImportMapJson {
+    #[serde(default)]
+    imports: HashMap<String, Option<String>>,
+    #[serde(default)]
+    scopes: HashMap<String, HashMap<String, Option<String>>>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ConfigJson {
+    import_map: Option<String>,
+    #[serde(flatten)]
+    map: ImportMapJson,
+}
+
+// Sorted by key in descending order, so that longer keys come before the keys they start with. A `None` value blocks
+// the specifier, it's what the JSON gives for `null` and for values that aren't valid.
+type SpecifierMap = Vec<(String, Option<ModuleSpecifier>)>;
+
+#[derive(Debug, Default, Clone)]
+pub struct ImportMap {
+    imports: SpecifierMap,
+    scopes: Vec<(String, SpecifierMap)>,
+}
+
+impl ImportMap {
+    /// Parses an import map, resolving the URLs in it against `base`, the URL of the file it came from.
+    pub fn from_json(base: &ModuleSpecifier, json: &str) -> Result<Self, ImportMapError> {
+        let map: ImportMapJson =
+            serde_json::from_str(json).map_err(|e| ImportMapError::Invalid {
+                path: base.to_string(),
+                reason: e.to_string(),
+            })?;
+        Ok(Self::from_parsed(base, map))
+    }
+
+    fn from_parsed(base: &ModuleSpecifier, map: ImportMapJson) -> Self {
+        let mut scopes: Vec<_> = map
+            .scopes
+            .into_iter()
+            .filter_map(|(scope, imports)| {
+                let scope = base.join(&scope).ok()?;
+                Some((scope.to_string(), specifier_map(base, imports)))
+            })
+            .collect();
+        scopes.sort_by(|(a, _), (b, _)| b.cmp(a));
+
+        Self {
+            imports: specifier_map(base, map.imports),
+            scopes,
+        }
+    }
+
+    /// Loads the import map given with `--import-map`, or else the one in `runjs.json` in `cwd`, if there is one.
+    pub fn load(flag: Option<&Path>, cwd: &Path) -> Result<Option<Self>, ImportMapError> {
+        if let Some(path) = flag {
+            return Self::from_file(&cwd.join(path)).map(Some);
+        }
+
+        let path = cwd.join(CONFIG_FILE);
+        if !path.exists() {
+            return Ok(None);
+        }
+        let (base, contents) = read(&path)?;
+        let config: ConfigJson =
+            serde_json::from_str(&contents).map_err(|e| ImportMapError::Invalid {
+                path: path.display().to_string(),
+                reason: e.to_string(),
+            })?;
+        match config.import_map {
+            Some(import_map) => Self::from_file(&cwd.join(import_map)).map(Some),
+            None => Ok(Some(Self::from_parsed(&base, config.map))),
+        }
+    }
+
+    fn from_file(path: &Path) -> Result<Self, ImportMapError> {
+        let (base, contents) = read(path)?;
+        Self::from_json(&base, &contents)
+    }
+
+    /// Resolves the specifier imported from `referrer`, returning `None` if the import map doesn't mention it.
+    pub fn resolve(
+        &self,
+        specifier: &str,
+        referrer: &ModuleSpecifier,
+    ) -> Result<Option<ModuleSpecifier>, ImportMapError> {
+        let normalized = match parse_url_like(specifier, referrer) {
+            Some(url) => url.to_string(),
+            None => specifier.to_string(),
+        };
+
+        for (scope, imports) in &self.scopes {
+            let applies = scope == referrer.as_str()
+                || (scope.ends_with('/') && referrer.as_str().starts_with(scope.as_str()));
+            if applies && let Some(url) = resolve_in(imports, &normalized)? {
+                return Ok(Some(url));
+            }
+        }
+        resolve_in(&self.imports, &normalized)
+    }
+}
+
+/// Reads `--import-map=<path>` out of the arguments before the first positional one, returning the arguments left.
+pub fn import_map_flag(args: Vec<String>) -> (Option<PathBuf>, Vec<String>) {
+    let mut path = None;
+    let mut rest = Vec::new();
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        if !arg.starts_with('-') {
+            rest.push(arg);
+            rest.extend(args);
+            break;
+        }
+        match arg.strip_prefix("--import-map=") {
+            Some(value) => path = Some(PathBuf::from(value)),
+            None => rest.push(arg),
+        }
+    }
+    (path, rest)
+}
+
+fn read(path: &Path) -> Result<(ModuleSpecifier, String), ImportMapError> {
+    let contents = std::fs::read_to_string(path).map_err(|source| ImportMapError::Io {
+        path: path.display().to_string(),
+        source,
+    })?;
+    let base = ModuleSpecifier::from_file_path(path).map_err(|()| ImportMapError::Invalid {
+        path: path.display().to_string(),
+        reason: "the path must be absolute".to_string(),
+    })?;
+    Ok((base, contents))
+}
+
+fn specifier_map(base: &ModuleSpecifier, imports: HashMap<String, Option<String>>) -> SpecifierMap {
+    let mut map: SpecifierMap = imports
+        .into_iter()
+        .filter(|(key, _)| !key.is_empty())
+        .map(|(key, value)| {
+            let key = match parse_url_like(&key, base) {
+                Some(url) => url.to_string(),
+                None => key,
+            };
+            let value = value
+                .and_then(|value| parse_url_like(&value, base))
+                // A prefix has to map to a prefix.
+                .filter(|value| !key.ends_with('/') || value.as_str().ends_with('/'));
+            (key, value)
+        })
+        .collect();
+    map.sort_by(|(a, _), (b, _)| b.cmp(a));
+    map
+}
+
+// Relative specifiers resolve against the base, absolute URLs stand on their own, and anything else is bare.
+fn parse_url_like(specifier: &str, base: &ModuleSpecifier) -> Option<ModuleSpecifier> {
+    if specifier.starts_with('/') || specifier.starts_with("./") || specifier.starts_with("../") {
+        return base.join(specifier).ok();
+    }
+    ModuleSpecifier::parse(specifier).ok()
+}
+
+fn resolve_in(
+    imports: &SpecifierMap,
+    normalized: &str,
+) -> Result<Option<ModuleSpecifier>, ImportMapError> {
+    let blocked = |reason| ImportMapError::Blocked {
+        specifier: normalized.to_string(),
+        reason,
+    };
+
+    for (key, value) in imports {
+        if key == normalized {
+            let url = value
+                .clone()
+                .ok_or_else(|| blocked("its mapping is null or invalid"))?;
+            return Ok(Some(url));
+        }
+        if key.ends_with('/') && normalized.starts_with(key.as_str()) {
+            let prefix = value
+                .as_ref()
+                .ok_or_else(|| blocked("its mapping is null or invalid"))?;
+            let url = prefix
+                .join(&normalized[key.len()..])
+                .map_err(|_| blocked("it doesn't form a valid URL"))?;
+            // `lodash/../x` must not escape the directory `lodash/` maps to.
+            if !url.as_str().starts_with(prefix.as_str()) {
+                return Err(blocked("it backtracks above its prefix"));
+            }
+            return Ok(Some(url));
+        }
+    }
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn url(url: &str) -> ModuleSpecifier {
+        ModuleSpecifier::parse(url).unwrap()
+    }
+
+    fn sample_map() -> ImportMap {
+        let json = r#"{
+            "imports": {
+                "lodash": "./vendor/lodash/lodash.js",
+                "lodash/": "./vendor/lodash/",
+                "std/": "https://deno.land/std@0.177.0/",
+                "https://cdn.example.com/react.js": "./vendor/react.js",
+                "blocked": null,
+                "bad/": "./not-a-prefix.js"
+            },
+            "scopes": {
+                "./legacy/": {
+                    "lodash": "./vendor/lodash-v3/lodash.js"
+                },
+                "./legacy/old/": {
+                    "lodash": "./vendor/lodash-v2/lodash.js"
+                }
+            }
+        }"#;
+        ImportMap::from_json(&url("file:///app/import_map.json"), json).unwrap()
+    }
+
+    fn resolve(map: &ImportMap, specifier: &str, referrer: &str) -> Option<String> {
+        map.resolve(specifier, &url(referrer))
+            .unwrap()
+            .map(|url| url.to_string())
+    }
+
+    #[test]
+    fn test_bare_and_prefix_mappings() {
+        let map = sample_map();
+
+        assert_eq!(
+            resolve(&map, "lodash", "file:///app/main.ts").as_deref(),
+            Some("file:///app/vendor/lodash/lodash.js")
+        );
+        assert_eq!(
+            resolve(&map, "lodash/fp/map.js", "file:///app/main.ts").as_deref(),
+            Some("file:///app/vendor/lodash/fp/map.js")
+        );
+        assert_eq!(
+            resolve(&map, "std/path/mod.ts", "file:///app/main.ts").as_deref(),
+            Some("https://deno.land/std@0.177.0/path/mod.ts")
+        );
+        assert_eq!(
+            resolve(
+                &map,
+                "https://cdn.example.com/react.js",
+                "file:///app/main.ts"
+            )
+            .as_deref(),
+            Some("file:///app/vendor/react.js")
+        );
+        assert_eq!(resolve(&map, "./util.ts", "file:///app/main.ts"), None);
+        assert_eq!(resolve(&map, "preact", "file:///app/main.ts"), None);
+    }
+
+    #[test]
+    fn test_scopes() {
+        let map = sample_map();
+
+        assert_eq!(
+            resolve(&map, "lodash", "file:///app/legacy/main.ts").as_deref(),
+            Some("file:///app/vendor/lodash-v3/lodash.js")
+        );
+        // The most specific scope wins.
+        assert_eq!(
+            resolve(&map, "lodash", "file:///app/legacy/old/main.ts").as_deref(),
+            Some("file:///app/vendor/lodash-v2/lodash.js")
+        );
+        // Scopes fall back to the top-level imports.
+        assert_eq!(
+            resolve(&map, "lodash/fp/map.js", "file:///app/legacy/main.ts").as_deref(),
+            Some("file:///app/vendor/lodash/fp/map.js")
+        );
+    }
+
+    #[test]
+    fn test_blocked_specifiers() {
+        let map = sample_map();
+        let referrer = url("file:///app/main.ts");
+
+        for specifier in ["blocked", "bad/thing.js", "lodash/../../secret.js"] {
+            assert!(
+                matches!(
+                    map.resolve(specifier, &referrer),
+                    Err(ImportMapError::Blocked { .. })
+                ),
+                "{specifier} should be blocked"
+            );
+        }
+    }
+
+    #[test]
+    fn test_load_from_config() {
+        let dir = std::env::temp_dir().join(format!("runjs_{}_import_map", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let main = url(&format!("file://{}/main.ts", dir.display()));
+
+        std::fs::write(
+            dir.join(CONFIG_FILE),
+            r#"{ "imports": { "app/": "./src/" } }"#,
+        )
+        .unwrap();
+        let map = ImportMap::load(None, &dir).unwrap().unwrap();
+        assert_eq!(
+            map.resolve("app/mod.ts", &main).unwrap(),
+            Some(url(&format!("file://{}/src/mod.ts", dir.display())))
+        );
+
+        std::fs::write(dir.join(CONFIG_FILE), r#"{ "importMap": "./map.json" }"#).unwrap();
+        std::fs::write(
+            dir.join("map.json"),
+            r#"{ "imports": { "app/": "./lib/" } }"#,
+        )
+        .unwrap();
+        let map = ImportMap::load(None, &dir).unwrap().unwrap();
+        assert_eq!(
+            map.resolve("app/mod.ts", &main).unwrap(),
+            Some(url(&format!("file://{}/lib/mod.ts", dir.display())))
+        );
+
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert!(ImportMap::load(None, &dir).unwrap().is_none());
+        assert!(matches!(
+            ImportMap::load(Some(Path::new("missing.json")), &dir),
+            Err(ImportMapError::Io { .. })
+        ));
+    }
+}
@@ -0,0 +1,456 @@
+// `console` and the value inspection behind it, following what Node's `util.inspect` prints.
+
+const { core } = Deno;
+
+const styles = {
+    special: [36, 39],
+    number: [33, 39],
+    bigint: [33, 39],
+    boolean: [33, 39],
+    undefined: [90, 39],
+    null: [1, 22],
+    string: [32, 39],
+    symbol: [32, 39],
+    date: [35, 39],
+    regexp: [31, 39],
+};
+
+function stylize(text, style, ctx) {
+    if (!ctx.colors) {
+        return text;
+    }
+    const [open, close] = styles[style];
+    return `\x1b[${open}m${text}\x1b[${close}m`;
+}
+
+function visibleLength(text) {
+    return text.replace(/\x1b\[\d+m/g, "").length;
+}
+
+const escapes = {
+    "\n": "\\n",
+    "\t": "\\t",
+    "\r": "\\r",
+    "\b": "\\b",
+    "\f": "\\f",
+    "\v": "\\v",
+    "\\": "\\\\",
+};
+
+// Quotes with single quotes, unless the string contains them and another quote would avoid escaping.
+function quoteString(string) {
+    let quote = "'";
+    if (string.includes("'")) {
+        if (!string.includes('"')) {
+            quote = '"';
+        } else if (!string.includes("`") && !string.includes("${")) {
+            quote = "`";
+        }
+    }
+    let escaped = string.replace(
+        /[\\\x00-\x1f\x7f]/g,
+        (char) => escapes[char] ?? `\\x${char.charCodeAt(0).toString(16).padStart(2, "0")}`,
+    );
+    if (quote === "'") {
+        escaped = escaped.replaceAll("'", "\\'");
+    }
+    return `${quote}${escaped}${quote}`;
+}
+
+function formatKey(key, ctx) {
+    if (typeof key === "symbol") {
+        return `[${stylize(key.toString(), "symbol", ctx)}]`;
+    }
+    if (/^[a-zA-Z_$][a-zA-Z_$0-9]*$/.test(key)) {
+        return key;
+    }
+    return stylize(quoteString(key), "string", ctx);
+}
+
+function constructorName(value) {
+    const prototype = Object.getPrototypeOf(value);
+    if (prototype === null) {
+        return null;
+    }
+    return prototype.constructor?.name || "Object";
+}
+
+function functionBase(fn) {
+    if (Function.prototype.toString.call(fn).startsWith("class")) {
+        return `[class ${fn.name || "(anonymous)"}]`;
+    }
+    // AsyncFunction, GeneratorFunction and AsyncGeneratorFunction are named after their constructor.
+    const kind = constructorName(fn) ?? "Function";
+    return fn.name ? `[${kind}: ${fn.name}]` : `[${kind} (anonymous)]`;
+}
+
+function formatError(error) {
+    const stack = typeof error.stack === "string" ? error.stack : "";
+    return stack.length > 0 ? stack : `${error.name}: ${error.message}`;
+}
+
+function formatValue(value, ctx, level) {
+    switch (typeof value) {
+        case "string":
+            return stylize(quoteString(value), "string", ctx);
+        case "number":
+            return stylize(Object.is(value, -0) ? "-0" : String(value), "number", ctx);
+        case "bigint":
+            return stylize(`${value}n`, "bigint", ctx);
+        case "boolean":
+            return stylize(String(value), "boolean", ctx);
+        case "undefined":
+            return stylize("undefined", "undefined", ctx);
+        case "symbol":
+            return stylize(value.toString(), "symbol", ctx);
+    }
+    if (value === null) {
+        return stylize("null", "null", ctx);
+    }
+    return formatObject(value, ctx, level);
+}
+
+function formatProperty(value, key, ctx, level) {
+    const descriptor = Object.getOwnPropertyDescriptor(value, key);
+    let formatted;
+    if (descriptor.get && descriptor.set) {
+        formatted = stylize("[Getter/Setter]", "special", ctx);
+    } else if (descriptor.get) {
+        formatted = stylize("[Getter]", "special", ctx);
+    } else if (descriptor.set) {
+        formatted = stylize("[Setter]", "special", ctx);
+    } else {
+        formatted = formatValue(descriptor.value, ctx, level + 1);
+    }
+    return `${formatKey(key, ctx)}: ${formatted}`;
+}
+
+function ownKeys(value) {
+    const symbols = Object.getOwnPropertySymbols(value).filter((symbol) =>
+        Object.prototype.propertyIsEnumerable.call(value, symbol)
+    );
+    return [...Object.keys(value), ...symbols];
+}
+
+const maxArrayLength = 100;
+
+function formatList(list, ctx, level) {
+    const entries = [];
+    const shown = Math.min(list.length, maxArrayLength);
+    for (let i = 0; i < shown; i++) {
+        entries.push(i in list ? formatValue(list[i], ctx, level + 1) : stylize("<1 empty item>", "undefined", ctx));
+    }
+    if (list.length > shown) {
+        entries.push(`... ${list.length - shown} more item${list.length - shown === 1 ? "" : "s"}`);
+    }
+    return entries;
+}
+
+function formatObject(value, ctx, level) {
+    if (ctx.seen.includes(value)) {
+        return stylize("[Circular]", "special", ctx);
+    }
+    if (value instanceof Error) {
+        return formatError(value);
+    }
+    if (value instanceof Date) {
+        return stylize(Number.isNaN(value.getTime()) ? "Invalid Date" : value.toISOString(), "date", ctx);
+    }
+    if (value instanceof RegExp) {
+        return stylize(String(value), "regexp", ctx);
+    }
+
+    const name = constructorName(value);
+    const isIndexKey = (key) => typeof key === "string" && /^\d+$/.test(key);
+    let prefix = name === "Object" ? "" : name === null ? "[Object: null prototype] " : `${name} `;
+    let braces = ["{", "}"];
+    let keys = ownKeys(value);
+    let entries;
+
+    if (Array.isArray(value) || (ArrayBuffer.isView(value) && !(value instanceof DataView))) {
+        prefix = name === "Array" ? "" : `${name}(${value.length}) `;
+        braces = ["[", "]"];
+        keys = keys.filter((key) => !isIndexKey(key));
+        entries = () => formatList(value, ctx, level);
+    } else if (value instanceof Map) {
+        prefix = `${name}(${value.size}) `;
+        entries = () =>
+            [...value].map(([key, item]) =>
+                `${formatValue(key, ctx, level + 1)} => ${formatValue(item, ctx, level + 1)}`
+            );
+    } else if (value instanceof Set) {
+        prefix = `${name}(${value.size}) `;
+        entries = () => [...value].map((item) => formatValue(item, ctx, level + 1));
+    } else if (value instanceof ArrayBuffer) {
+        entries = () => [`byteLength: ${formatValue(value.byteLength, ctx, level + 1)}`];
+    } else if (typeof value === "function") {
+        const base = stylize(functionBase(value), "special", ctx);
+        if (keys.length === 0) {
+            return base;
+        }
+        prefix = `${base} `;
+        entries = () => [];
+    } else {
+        entries = () => [];
+    }
+
+    if (level > ctx.depth) {
+        return stylize(Array.isArray(value) ? "[Array]" : `[${name ?? "Object"}]`, "special", ctx);
+    }
+
+    ctx.seen.push(value);
+    const output = [...entries(), ...keys.map((key) => formatProperty(value, key, ctx, level))];
+    ctx.seen.pop();
+
+    return reduceToSingleLine(prefix, braces, output, level);
+}
+
+const breakLength = 80;
+
+function reduceToSingleLine(prefix, [open, close], output, level) {
+    const start = `${prefix}${open}`;
+    if (output.length === 0) {
+        return `${start}${close}`;
+    }
+
+    const totalLength = output.reduce((sum, entry) => sum + visibleLength(entry), output.length) +
+        visibleLength(start) + level * 2 + 10;
+    if (totalLength <= breakLength && !output.some((entry) => entry.includes("\n"))) {
+        return `${start} ${output.join(", ")} ${close}`;
+    }
+    const lines = output.map((entry) => `  ${entry.replaceAll("\n", "\n  ")}`);
+    return `${start}\n${lines.join(",\n")}\n${close}`;
+}
+
+/**
+ * Returns a string representation of the value, like Node's `util.inspect`. `depth` is how many levels of nested
+ * objects are shown, 2 by default, and `colors` styles the output with ANSI escape codes.
+ */
+export function inspect(value, { depth = 2, colors = false } = {}) {
+    const ctx = { depth: depth ?? Infinity, colors, seen: [] };
+    return formatValue(value, ctx, 0);
+}
+
+function formatArg(arg, colors) {
+    return typeof arg === "string" ? arg : inspect(arg, { colors });
+}
+
+function formatWithOptions({ colors = false }, args) {
+    let start = 0;
+    const parts = [];
+
+    const [first] = args;
+    if (typeof first === "string" && args.length > 1) {
+        start = 1;
+        parts.push(first.replace(/%([sdifjoOc%])/g, (match, type) => {
+            if (type === "%") {
+                return "%";
+            }
+            if (start >= args.length) {
+                return match;
+            }
+            const arg = args[start++];
+            switch (type) {
+                case "s":
+                    if (typeof arg === "string") {
+                        return arg;
+                    }
+                    return typeof arg === "object" && arg !== null
+                        ? inspect(arg, { depth: 0, colors })
+                        : typeof arg === "bigint"
+                        ? `${arg}n`
+                        : String(arg);
+                case "d":
+                    return typeof arg === "bigint" ? `${arg}n` : typeof arg === "symbol" ? "NaN" : String(Number(arg));
+                case "i":
+                    return typeof arg === "bigint" ? `${arg}n` : typeof arg === "symbol" ? "NaN" : String(parseInt(arg));
+                case "f":
+                    return typeof arg === "symbol" ? "NaN" : String(parseFloat(arg));
+                case "j":
+                    try {
+                        return JSON.stringify(arg);
+                    } catch {
+                        return "[Circular]";
+                    }
+                case "o":
+                    return inspect(arg, { depth: 4, colors });
+                case "O":
+                    return inspect(arg, { colors });
+                case "c":
+                    return "";
+            }
+        }));
+    }
+
+    for (const arg of args.slice(start)) {
+        parts.push(formatArg(arg, colors));
+    }
+    return parts.join(" ");
+}
+
+/** Formats the arguments the way `console.log` prints them, substituting `%s`, `%d`, `%o` and friends. */
+export function format(...args) {
+    return formatWithOptions({}, args);
+}
+
+// Whether to print in colour is asked on first use, since this module is evaluated while building the snapshot,
+// where no ops are available.
+const colorsByStream = new Map();
+
+function useColors(isErr) {
+    if (!colorsByStream.has(isErr)) {
+        colorsByStream.set(isErr, core.ops.op_use_colors(isErr));
+    }
+    return colorsByStream.get(isErr);
+}
+
+function renderTable(header, rows) {
+    const widths = header.map((column) =>
+        Math.max(column.length, ...rows.map((row) => (row[column] ?? "").length)) + 2
+    );
+    const line = (left, middle, right) => `${left}${widths.map((width) => "─".repeat(width)).join(middle)}${right}`;
+    const row = (cells) =>
+        `│${header.map((column, i) => ` ${(cells[column] ?? "").padEnd(widths[i] - 2)} `).join("│")}│`;
+
+    return [
+        line("┌", "┬", "┐"),
+        row(Object.fromEntries(header.map((column) => [column, column]))),
+        line("├", "┼", "┤"),
+        ...rows.map(row),
+        line("└", "┴", "┘"),
+    ].join("\n");
+}
+
+export function createConsole() {
+    let indentation = "";
+    const counts = new Map();
+    const timers = new Map();
+
+    const print = (isErr, args) => {
+        let message = formatWithOptions({ colors: useColors(isErr) }, args);
+        if (indentation.length > 0) {
+            message = indentation + message.replaceAll("\n", `\n${indentation}`);
+        }
+        core.print(`${message}\n`, isErr);
+    };
+
+    const elapsed = (label, method) => {
+        if (!timers.has(label)) {
+            print(true, [`Warning: No such label '${label}' for console.${method}()`]);
+            return null;
+        }
+        return `${label}: ${Date.now() - timers.get(label)}ms`;
+    };
+
+    const console = {
+        log: (...args) => print(false, args),
+        info: (...args) => print(false, args),
+        debug: (...args) => print(false, args),
+        error: (...args) => print(true, args),
+        warn: (...args) => print(true, args),
+
+        dir: (value, options = {}) => {
+            print(false, [inspect(value, { ...options, colors: options.colors ?? useColors(false) })]);
+        },
+
+        trace: (...args) => {
+            const message = args.length > 0 ? `Trace: ${format(...args)}` : "Trace";
+            // Drop the first line, the error message, and the frame of `trace` itself.
+            const frames = (new Error().stack ?? "").split("\n").slice(2);
+            print(true, [[message, ...frames].join("\n")]);
+        },
+
+        assert: (condition, ...args) => {
+            if (condition) {
+                return;
+            }
+            if (typeof args[0] === "string") {
+                args[0] = `Assertion failed: ${args[0]}`;
+            } else {
+                args.unshift("Assertion failed");
+            }
+            print(true, args);
+        },
+
+        group: (...label) => {
+            if (label.length > 0) {
+                print(false, label);
+            }
+            indentation += "  ";
+        },
+
+        groupEnd: () => {
+            indentation = indentation.slice(2);
+        },
+
+        count: (label = "default") => {
+            label = String(label);
+            const count = (counts.get(label) ?? 0) + 1;
+            counts.set(label, count);
+            print(false, [`${label}: ${count}`]);
+        },
+
+        countReset: (label = "default") => {
+            counts.delete(String(label));
+        },
+
+        time: (label = "default") => {
+            label = String(label);
+            if (timers.has(label)) {
+                print(true, [`Warning: Label '${label}' already exists for console.time()`]);
+                return;
+            }
+            timers.set(label, Date.now());
+        },
+
+        timeLog: (label = "default", ...data) => {
+            const message = elapsed(String(label), "timeLog");
+            if (message !== null) {
+                print(false, [message, ...data]);
+            }
+        },
+
+        timeEnd: (label = "default") => {
+            label = String(label);
+            const message = elapsed(label, "timeEnd");
+            if (message !== null) {
+                timers.delete(label);
+                print(false, [message]);
+            }
+        },
+
+        table: (data, properties = undefined) => {
+            if (data === null || typeof data !== "object") {
+                print(false, [data]);
+                return;
+            }
+
+            const cell = (value) => inspect(value, { depth: 0 });
+            const entries = data instanceof Map || data instanceof Set
+                ? [...data.entries()].map(([key, value], i) => [data instanceof Map ? cell(key) : i, value])
+                : Object.entries(data);
+            const columns = [];
+            let hasValues = false;
+            const rows = entries.map(([index, row]) => {
+                const cells = { "(index)": String(index) };
+                if (row !== null && typeof row === "object") {
+                    for (const [key, value] of Object.entries(row)) {
+                        if (!columns.includes(key)) {
+                            columns.push(key);
+                        }
+                        cells[key] = cell(value);
+                    }
+                } else {
+                    hasValues = true;
+                    cells.Values = cell(row);
+                }
+                return cells;
+            });
+
+            const header = ["(index)", ...(properties ?? columns), ...(hasValues ? ["Values"] : [])];
+            print(false, [renderTable(header, rows)]);
+        },
+    };
+    console.groupCollapsed = console.group;
+    return console;
+}
@@ -1,16 +1,231 @@
+import { createConsole, format, inspect } from "ext:runjs/console.js";
+import {
+    AssertionError,
+    assert,
+    assertEquals,
+    assertNotEquals,
+    assertRejects,
+    assertStrictEquals,
+    assertThrows,
+    fail,
+    runTests,
+    test,
+} from "ext:runjs/testing.js";
+
 const { core } = Deno;
 
-function argsToMessage(...args) {
-    return args.map((arg) => JSON.stringify(arg)).join("");
+// Raised by the ops when the script wasn't granted access to a path or host.
+class PermissionDenied extends Error {
+    constructor(message) {
+        super(message);
+        this.name = "PermissionDenied";
+    }
 }
+core.registerErrorClass("PermissionDenied", PermissionDenied);
 
-globalThis.console = {
-    log: (...args) => {
-        core.print(`[out]: ${argsToMessage(...args)}\n`, false);
-    },
-    error: (...args) => {
-        core.print(`[err]: ${argsToMessage(...args)}\n`, true);
-    },
+globalThis.console = createConsole();
+
+// Header names are case-insensitive, so they are kept lowercased.
+class Headers {
+    #entries = [];
+
+    constructor(init = undefined) {
+        if (init === undefined) {
+            return;
+        }
+        const entries = init instanceof Headers || Array.isArray(init)
+            ? [...init]
+            : Object.entries(init);
+        for (const [name, value] of entries) {
+            this.append(name, value);
+        }
+    }
+
+    append(name, value) {
+        this.#entries.push([String(name).toLowerCase(), String(value)]);
+    }
+
+    set(name, value) {
+        this.delete(name);
+        this.append(name, value);
+    }
+
+    get(name) {
+        const values = this.#entries
+            .filter(([key]) => key === String(name).toLowerCase())
+            .map(([, value]) => value);
+        return values.length === 0 ? null : values.join(", ");
+    }
+
+    has(name) {
+        return this.get(name) !== null;
+    }
+
+    delete(name) {
+        this.#entries = this.#entries.filter(([key]) => key !== String(name).toLowerCase());
+    }
+
+    forEach(callback, thisArg = undefined) {
+        for (const [name, value] of this) {
+            callback.call(thisArg, value, name, this);
+        }
+    }
+
+    *entries() {
+        const names = [...new Set(this.#entries.map(([name]) => name))].sort();
+        for (const name of names) {
+            yield [name, this.get(name)];
+        }
+    }
+
+    *keys() {
+        for (const [name] of this.entries()) {
+            yield name;
+        }
+    }
+
+    *values() {
+        for (const [, value] of this.entries()) {
+            yield value;
+        }
+    }
+
+    [Symbol.iterator]() {
+        return this.entries();
+    }
+}
+
+// Turns a request or response body into bytes, returning null for no body.
+function bodyToBytes(body) {
+    if (body === undefined || body === null) {
+        return null;
+    }
+    if (typeof body === "string") {
+        return core.encode(body);
+    }
+    if (body instanceof ArrayBuffer) {
+        return new Uint8Array(body.slice(0));
+    }
+    if (ArrayBuffer.isView(body)) {
+        return new Uint8Array(body.buffer.slice(body.byteOffset, body.byteOffset + body.byteLength));
+    }
+    throw new TypeError("Body must be a string, an ArrayBuffer or an ArrayBufferView");
+}
+
+class Request {
+    constructor(input, init = {}) {
+        const base = input instanceof Request ? input : null;
+        this.url = base ? base.url : String(input);
+        this.method = (init.method ?? base?.method ?? "GET").toUpperCase();
+        this.headers = new Headers(init.headers ?? base?.headers);
+        this.body = init.body !== undefined ? bodyToBytes(init.body) : (base?.body ?? null);
+
+        if (this.body !== null && (this.method === "GET" || this.method === "HEAD")) {
+            throw new TypeError(`Request with ${this.method} method cannot have a body`);
+        }
+        if (typeof init.body === "string" && !this.headers.has("content-type")) {
+            this.headers.set("content-type", "text/plain;charset=UTF-8");
+        }
+    }
+}
+
+class Response {
+    #body;
+
+    constructor(body = null, init = {}) {
+        this.#body = bodyToBytes(body) ?? new Uint8Array();
+        this.status = init.status ?? 200;
+        this.statusText = init.statusText ?? "";
+        this.headers = new Headers(init.headers);
+        this.url = init.url ?? "";
+        this.bodyUsed = false;
+    }
+
+    get ok() {
+        return this.status >= 200 && this.status < 300;
+    }
+
+    async arrayBuffer() {
+        if (this.bodyUsed) {
+            throw new TypeError("Body has already been consumed");
+        }
+        this.bodyUsed = true;
+        return this.#body.buffer.slice(this.#body.byteOffset, this.#body.byteOffset + this.#body.byteLength);
+    }
+
+    async text() {
+        return core.decode(new Uint8Array(await this.arrayBuffer()));
+    }
+
+    async json() {
+        return JSON.parse(await this.text());
+    }
+}
+
+async function fetch(input, init = undefined) {
+    const request = new Request(input, init);
+    const response = await core.ops.op_fetch({
+        url: request.url,
+        method: request.method,
+        headers: [...request.headers],
+        body: request.body,
+    });
+    return new Response(response.body, response);
+}
+
+globalThis.Headers = Headers;
+globalThis.Request = Request;
+globalThis.Response = Response;
+globalThis.fetch = fetch;
+
+// Timer ids are resource ids, so only timers that are still running get cleared on the Rust side.
+const activeTimers = new Set();
+
+function startTimer(callback, delay, args, repeat) {
+    if (typeof callback !== "function") {
+        throw new TypeError("Timer callback must be a function");
+    }
+    delay = Math.max(0, Number(delay) || 0);
+
+    const id = core.ops.op_timer_start();
+    activeTimers.add(id);
+    (async () => {
+        do {
+            if (!(await core.ops.op_timer_sleep(id, delay))) {
+                return;
+            }
+            if (!repeat) {
+                clearTimer(id);
+            }
+            try {
+                callback(...args);
+            } catch (error) {
+                // A throwing callback is an uncaught error, not a rejected promise, and an interval stops so it
+                // doesn't keep the event loop alive.
+                clearTimer(id);
+                core.reportUnhandledException(error);
+                return;
+            }
+        } while (repeat);
+    })();
+    return id;
+}
+
+function clearTimer(id) {
+    if (activeTimers.delete(id)) {
+        core.ops.op_timer_clear(id);
+    }
+}
+
+globalThis.setTimeout = (callback, delay = 0, ...args) => startTimer(callback, delay, args, false);
+globalThis.setInterval = (callback, delay = 0, ...args) => startTimer(callback, delay, args, true);
+globalThis.clearTimeout = clearTimer;
+globalThis.clearInterval = clearTimer;
+globalThis.queueMicrotask = (callback) => {
+    if (typeof callback !== "function") {
+        throw new TypeError("Microtask callback must be a function");
+    }
+    Promise.resolve().then(() => callback());
 };
 
 globalThis.runjs = {
@@ -23,7 +238,21 @@ globalThis.runjs = {
     removeFile: (path) => {
         return core.ops.op_remove_file(path);
     },
-    fetch: (url) => {
-        return core.ops.op_fetch(url);
+    inspect,
+    format,
+    test,
+    assert,
+    assertEquals,
+    assertNotEquals,
+    assertStrictEquals,
+    assertThrows,
+    assertRejects,
+    fail,
+    errors: {
+        PermissionDenied,
+        AssertionError,
     },
 };
+
+// For `runjs test` to run the tests once a test file has loaded.
+globalThis[Symbol.for("runjs.runTests")] = runTests;
@@ -0,0 +1,199 @@
+// `runjs.test` and the assertions for tests. Registering a test doesn't run it, `runjs test` runs the tests of each
+// file once it has loaded, through `runTests`, and reports every result to the `op_test_event` op.
+
+import { inspect } from "ext:runjs/console.js";
+
+const { core } = Deno;
+
+export class AssertionError extends Error {
+    constructor(message) {
+        super(message);
+        this.name = "AssertionError";
+    }
+}
+
+const tests = [];
+
+/**
+ * Registers a test. The function may be async, and fails the test by throwing. `timeout` overrides the time in
+ * milliseconds the test may take, and `ignore` skips it.
+ */
+export function test(name, fn, { timeout = undefined, ignore = false } = {}) {
+    if (typeof name !== "string" || typeof fn !== "function") {
+        throw new TypeError("runjs.test expects a name and a function");
+    }
+    tests.push({ name, fn, timeout, ignore });
+}
+
+// Compares values structurally, objects by their own enumerable properties. Cyclic values are equal if their cycles
+// are in the same places.
+function equal(a, b, seen = new Map()) {
+    if (Object.is(a, b)) {
+        return true;
+    }
+    if (typeof a !== "object" || typeof b !== "object" || a === null || b === null) {
+        return false;
+    }
+    if (Object.getPrototypeOf(a) !== Object.getPrototypeOf(b)) {
+        return false;
+    }
+    if (seen.get(a) === b) {
+        return true;
+    }
+    seen.set(a, b);
+
+    if (a instanceof Date) {
+        return Object.is(a.getTime(), b.getTime());
+    }
+    if (a instanceof RegExp) {
+        return String(a) === String(b);
+    }
+    if (a instanceof Error && (a.name !== b.name || a.message !== b.message)) {
+        return false;
+    }
+    if (a instanceof ArrayBuffer || ArrayBuffer.isView(a)) {
+        const bytes = (value) =>
+            value instanceof ArrayBuffer
+                ? new Uint8Array(value)
+                : new Uint8Array(value.buffer, value.byteOffset, value.byteLength);
+        const [left, right] = [bytes(a), bytes(b)];
+        return left.length === right.length && left.every((byte, index) => byte === right[index]);
+    }
+    if (a instanceof Map) {
+        if (a.size !== b.size) {
+            return false;
+        }
+        for (const [key, value] of a) {
+            if (!b.has(key) || !equal(value, b.get(key), seen)) {
+                return false;
+            }
+        }
+        return true;
+    }
+    if (a instanceof Set) {
+        return a.size === b.size && [...a].every((value) => b.has(value));
+    }
+    if (Array.isArray(a) && a.length !== b.length) {
+        return false;
+    }
+
+    const keys = Object.keys(a);
+    if (keys.length !== Object.keys(b).length) {
+        return false;
+    }
+    return keys.every((key) => Object.hasOwn(b, key) && equal(a[key], b[key], seen));
+}
+
+export function assert(condition, message = "Assertion failed") {
+    if (!condition) {
+        throw new AssertionError(message);
+    }
+}
+
+export function assertEquals(actual, expected, message = undefined) {
+    if (!equal(actual, expected)) {
+        throw new AssertionError(
+            message ?? `Values are not equal:\n  actual:   ${inspect(actual)}\n  expected: ${inspect(expected)}`,
+        );
+    }
+}
+
+export function assertNotEquals(actual, expected, message = undefined) {
+    if (equal(actual, expected)) {
+        throw new AssertionError(message ?? `Expected a value other than ${inspect(expected)}`);
+    }
+}
+
+export function assertStrictEquals(actual, expected, message = undefined) {
+    if (!Object.is(actual, expected)) {
+        throw new AssertionError(
+            message ?? `Values are not the same:\n  actual:   ${inspect(actual)}\n  expected: ${inspect(expected)}`,
+        );
+    }
+}
+
+export function fail(message = "Failed") {
+    throw new AssertionError(message);
+}
+
+function checkError(error, ErrorClass, messageIncludes) {
+    if (ErrorClass && !(error instanceof ErrorClass)) {
+        throw new AssertionError(`Expected the error to be a ${ErrorClass.name}, got ${inspect(error)}`);
+    }
+    if (messageIncludes !== undefined && !String(error?.message).includes(messageIncludes)) {
+        throw new AssertionError(
+            `Expected the error message to include ${inspect(messageIncludes)}, got ${inspect(error?.message)}`,
+        );
+    }
+    return error;
+}
+
+/** Asserts that `fn` throws, optionally an instance of `ErrorClass` with `messageIncludes` in its message. */
+export function assertThrows(fn, ErrorClass = undefined, messageIncludes = undefined) {
+    try {
+        fn();
+    } catch (error) {
+        return checkError(error, ErrorClass, messageIncludes);
+    }
+    throw new AssertionError("Expected the function to throw");
+}
+
+/** Asserts that the promise returned by `fn` rejects, like `assertThrows`. */
+export async function assertRejects(fn, ErrorClass = undefined, messageIncludes = undefined) {
+    try {
+        await fn();
+    } catch (error) {
+        return checkError(error, ErrorClass, messageIncludes);
+    }
+    throw new AssertionError("Expected the promise to reject");
+}
+
+// A filter wrapped in slashes is a regular expression, anything else has to be part of the name.
+function matcher(filter) {
+    if (filter === undefined || filter === null) {
+        return () => true;
+    }
+    if (filter.length > 1 && filter.startsWith("/") && filter.endsWith("/")) {
+        const regex = new RegExp(filter.slice(1, -1));
+        return (name) => regex.test(name);
+    }
+    return (name) => name.includes(filter);
+}
+
+function withTimeout(fn, timeout) {
+    const run = Promise.resolve().then(() => fn());
+    if (!timeout) {
+        return run;
+    }
+    let timer;
+    const timedOut = new Promise((_, reject) => {
+        timer = setTimeout(() => reject(new Error(`Test timed out after ${timeout}ms`)), timeout);
+    });
+    return Promise.race([run, timedOut]).finally(() => clearTimeout(timer));
+}
+
+/** Runs the registered tests one after the other, those whose name matches `filter`. */
+export async function runTests({ filter = undefined, timeout = undefined } = {}) {
+    const matches = matcher(filter);
+    const selected = tests.filter(({ name }) => matches(name));
+    core.ops.op_test_event({ type: "plan", total: selected.length, filtered: tests.length - selected.length });
+
+    for (const test of selected) {
+        const report = (outcome, started, error = undefined) => {
+            const duration = started === undefined ? 0 : Date.now() - started;
+            core.ops.op_test_event({ type: "result", name: test.name, outcome, duration, error });
+        };
+        if (test.ignore) {
+            report("ignored");
+            continue;
+        }
+
+        const started = Date.now();
+        try {
+            await withTimeout(test.fn, test.timeout ?? timeout);
+            report("ok", started);
+        } catch (error) {
+            report("failed", started, error instanceof Error ? (error.stack ?? String(error)) : inspect(error));
+        }
+    }
+}
@@ -1,12 +1,27 @@
-use deno_ast::MediaType;
-use deno_ast::ParseParams;
-use deno_core::ModuleLoadResponse;
-use deno_core::ModuleSourceCode;
+mod fetch;
+mod import_map;
+mod module_loader;
+mod node_modules;
+mod permissions;
+mod remote;
+mod repl;
+mod test_runner;
+#[cfg(test)]
+mod test_util;
+mod timers;
+
+use deno_core::OpState;
 use deno_core::error::AnyError;
-use deno_core::error::ModuleLoaderError;
 use deno_core::extension;
 use deno_core::op2;
+use import_map::ImportMap;
+use module_loader::TsModuleLoader;
+use permissions::{PermissionDenied, Permissions, SharedPermissions};
+use remote::{RemoteModules, RemoteOptions};
+use std::cell::RefCell;
+use std::io::IsTerminal;
 use std::rc::Rc;
+use test_runner::TestOptions;
 
 static RUNTIME_SNAPSHOT: &[u8] = include_bytes!(concat!(env!("OUT_DIR"), "/RUNJS_SNAPSHOT.bin"));
 
@@ -19,11 +34,25 @@ enum RuntimeError {
     #[class("HttpError")]
     #[error("Http error: {0}")]
     Http(String),
+
+    #[class(inherit)]
+    #[error(transparent)]
+    Permission(#[inherit] PermissionDenied),
 }
 
 #[op2(async)]
 #[string]
-async fn op_read_file(#[string] path: String) -> Result<String, RuntimeError> {
+async fn op_read_file(
+    state: Rc<RefCell<OpState>>,
+    #[string] path: String,
+) -> Result<String, RuntimeError> {
+    let path = state
+        .borrow()
+        .borrow::<SharedPermissions>()
+        .lock()
+        .unwrap()
+        .check_read(&path)
+        .map_err(RuntimeError::Permission)?;
     let contents = tokio::fs::read_to_string(path)
         .await
         .map_err(RuntimeError::Io)?;
@@ -33,9 +62,17 @@ async fn op_read_file(#[string] path: String) -> Result<String, RuntimeError> {
 #[op2(async)]
 #[string]
 async fn op_write_file(
+    state: Rc<RefCell<OpState>>,
     #[string] path: String,
     #[string] contents: String,
 ) -> Result<(), RuntimeError> {
+    let path = state
+        .borrow()
+        .borrow::<SharedPermissions>()
+        .lock()
+        .unwrap()
+        .check_write(&path)
+        .map_err(RuntimeError::Permission)?;
     tokio::fs::write(path, contents)
         .await
         .map_err(RuntimeError::Io)?;
@@ -44,142 +81,424 @@ async fn op_write_file(
 
 #[op2(fast)]
 #[string]
-fn op_remove_file(#[string] path: String) -> Result<(), RuntimeError> {
+fn op_remove_file(state: &mut OpState, #[string] path: String) -> Result<(), RuntimeError> {
+    let path = state
+        .borrow::<SharedPermissions>()
+        .lock()
+        .unwrap()
+        .check_write(&path)
+        .map_err(RuntimeError::Permission)?;
     std::fs::remove_file(path).map_err(RuntimeError::Io)?;
     Ok(())
 }
 
-#[op2(async)]
-#[string]
-async fn op_fetch(#[string] url: String) -> Result<String, RuntimeError> {
-    let response = reqwest::get(url)
-        .await
-        .map_err(|e| RuntimeError::Http(e.to_string()))?;
-    let body = response
-        .text()
-        .await
-        .map_err(|e| RuntimeError::Http(e.to_string()))?;
-    Ok(body)
-}
-
-struct TsModuleLoader;
-
-impl deno_core::ModuleLoader for TsModuleLoader {
-    fn resolve(
-        &self,
-        specifier: &str,
-        referrer: &str,
-        _kind: deno_core::ResolutionKind,
-    ) -> Result<deno_core::ModuleSpecifier, deno_core::error::ModuleLoaderError> {
-        deno_core::resolve_import(specifier, referrer).map_err(|e| e.into())
-    }
-
-    fn load(
-        &self,
-        module_specifier: &deno_core::ModuleSpecifier,
-        _maybe_referrer: Option<&deno_core::ModuleSpecifier>,
-        _is_dyn_import: bool,
-        _requested_module_type: deno_core::RequestedModuleType,
-    ) -> ModuleLoadResponse {
-        let module_specifier = module_specifier.clone();
-        let module_load = move || {
-            let path = module_specifier.to_file_path().unwrap();
-
-            let media_type = MediaType::from_path(&path);
-            let (module_type, should_transpile) = match media_type {
-                MediaType::JavaScript | MediaType::Mjs | MediaType::Cjs => {
-                    (deno_core::ModuleType::JavaScript, false)
-                }
-                MediaType::Jsx => (deno_core::ModuleType::JavaScript, true),
-                MediaType::TypeScript
-                | MediaType::Cts
-                | MediaType::Dts
-                | MediaType::Dmts
-                | MediaType::Dcts
-                | MediaType::Tsx => (deno_core::ModuleType::JavaScript, true),
-                MediaType::Json => (deno_core::ModuleType::Json, false),
-                _ => panic!("Unknown extension {:?}", path.extension()),
-            };
-
-            // Read and transpile
-            let code = std::fs::read_to_string(&path)?;
-            let code = if should_transpile {
-                let parsed = deno_ast::parse_module(ParseParams {
-                    specifier: module_specifier.clone(),
-                    text: code.into(),
-                    media_type,
-                    capture_tokens: false,
-                    scope_analysis: false,
-                    maybe_syntax: None,
-                })
-                .map_err(|_err| ModuleLoaderError::NotFound)?;
-                parsed
-                    .transpile(
-                        &Default::default(),
-                        &Default::default(),
-                        &Default::default(),
-                    )
-                    .map_err(|_err| ModuleLoaderError::Unsupported {
-                        specifier: Box::new(module_specifier.clone()),
-                        maybe_referrer: None,
-                    })?
-                    .into_source()
-                    .text
-                    .into_bytes()
-            } else {
-                code.into_bytes()
-            };
-
-            let module = deno_core::ModuleSource::new(
-                module_type,
-                ModuleSourceCode::Bytes(code.into_boxed_slice().into()),
-                &module_specifier,
-                None,
-            );
-            Ok(module)
-        };
-        ModuleLoadResponse::Sync(module_load())
-    }
+/// Whether console output to stdout, or stderr, should be coloured. `NO_COLOR` turns colours off.
+#[op2(fast)]
+fn op_use_colors(is_err: bool) -> bool {
+    let is_terminal = if is_err {
+        std::io::stderr().is_terminal()
+    } else {
+        std::io::stdout().is_terminal()
+    };
+    is_terminal && std::env::var_os("NO_COLOR").is_none()
 }
 
 extension!(
     runjs,
-    ops = [op_read_file, op_write_file, op_remove_file, op_fetch],
+    ops = [
+        op_read_file,
+        op_write_file,
+        op_remove_file,
+        op_use_colors,
+        fetch::op_fetch,
+        test_runner::op_test_event,
+        timers::op_timer_start,
+        timers::op_timer_sleep,
+        timers::op_timer_clear,
+    ],
+    options = {
+        permissions: SharedPermissions,
+    },
+    state = |state, options| {
+        state.put(fetch::client(options.permissions.clone()));
+        state.put(options.permissions);
+    },
 );
 
-async fn run_js(file_path: &str) -> Result<(), AnyError> {
-    let main_module = deno_core::resolve_path(file_path, &std::env::current_dir()?)?;
-    let mut js_runtime = deno_core::JsRuntime::new(deno_core::RuntimeOptions {
-        module_loader: Some(Rc::new(TsModuleLoader)),
-        extensions: vec![runjs::init()],
+fn create_runtime(
+    permissions: Permissions,
+    remote_options: RemoteOptions,
+    import_map: Option<ImportMap>,
+) -> Result<(deno_core::JsRuntime, Rc<TsModuleLoader>), AnyError> {
+    let permissions = SharedPermissions::new(permissions.into());
+    let loader = Rc::new(TsModuleLoader::new(
+        RemoteModules::new(remote_options, permissions.clone())?,
+        import_map,
+        permissions.clone(),
+    ));
+    let js_runtime = deno_core::JsRuntime::new(deno_core::RuntimeOptions {
+        module_loader: Some(loader.clone()),
+        extensions: vec![runjs::init(permissions)],
         startup_snapshot: Some(RUNTIME_SNAPSHOT),
         ..Default::default()
     });
+    Ok((js_runtime, loader))
+}
 
-    let mod_id = js_runtime.load_main_es_module(&main_module).await?;
-    let result = js_runtime.mod_evaluate(mod_id);
-    js_runtime.run_event_loop(Default::default()).await?;
+async fn run_js(
+    file_path: &str,
+    permissions: Permissions,
+    remote_options: RemoteOptions,
+    import_map: Option<ImportMap>,
+) -> Result<(), AnyError> {
+    let main_module = deno_core::resolve_path(file_path, &std::env::current_dir()?)?;
+    let (mut js_runtime, loader) = create_runtime(permissions, remote_options, import_map)?;
 
-    result.await?;
-    Ok(())
+    let result = async {
+        let mod_id = js_runtime.load_main_es_module(&main_module).await?;
+        let result = js_runtime.mod_evaluate(mod_id);
+        js_runtime.run_event_loop(Default::default()).await?;
+        result.await?;
+        Ok::<_, AnyError>(())
+    }
+    .await;
+
+    // The stack trace is already source mapped, show the original code where the error was thrown below it.
+    result.map_err(|error| {
+        let message = error.to_string();
+        match loader.code_frame_for(&message) {
+            Some(frame) => AnyError::msg(format!("{message}\n\n{frame}")),
+            None => error,
+        }
+    })
 }
 
 // Main entry point
 fn main() {
-    let args: Vec<String> = std::env::args().collect();
+    let (permissions, args) = Permissions::from_args(std::env::args().skip(1).collect());
+    let (remote_options, args) = RemoteOptions::from_args(args);
+    let (import_map_path, args) = import_map::import_map_flag(args);
 
-    if args.is_empty() {
-        eprintln!("Usage: runjs <file>");
-        std::process::exit(1);
+    if args
+        .first()
+        .is_some_and(|arg| arg == "--help" || arg == "-h")
+    {
+        println!(
+            "Usage: runjs [--allow-read[=<paths>]] [--allow-write[=<paths>]] [--allow-net[=<hosts>]] [--prompt] [--reload] [--cached-only] [--lock=<path> | --no-lock] [--import-map=<path>] [<file> | test [--filter=<text>] [--timeout=<ms>] [<paths>...]]"
+        );
+        println!(
+            "Runs the file, or the tests under the paths with `test`, or starts a REPL without either."
+        );
+        return;
     }
-    let file_path = &args[1];
+
+    let cwd = std::env::current_dir().unwrap();
+    let import_map = match ImportMap::load(import_map_path.as_deref(), &cwd) {
+        Ok(import_map) => import_map,
+        Err(error) => {
+            eprintln!("error: {}", error);
+            std::process::exit(1);
+        }
+    };
 
     let runtime = tokio::runtime::Builder::new_current_thread()
         .enable_all()
         .build()
         .unwrap();
 
-    if let Err(error) = runtime.block_on(run_js(file_path)) {
+    let result = match args.first() {
+        Some(command) if command == "test" => {
+            TestOptions::from_args(args[1..].to_vec()).and_then(|options| {
+                let summary = runtime.block_on(test_runner::run_tests(
+                    &options,
+                    permissions,
+                    remote_options,
+                    import_map,
+                ))?;
+                if summary.failed > 0 {
+                    std::process::exit(1);
+                }
+                Ok(())
+            })
+        }
+        Some(file_path) => {
+            runtime.block_on(run_js(file_path, permissions, remote_options, import_map))
+        }
+        None => {
+            let history_path = remote_options.cache_dir.join("repl_history.txt");
+            runtime.block_on(async {
+                let (js_runtime, _) = create_runtime(permissions, remote_options, import_map)?;
+                repl::run_repl(js_runtime, &history_path).await
+            })
+        }
+    };
+    if let Err(error) = result {
         eprintln!("error: {}", error);
+        std::process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::import_map::ImportMap;
+    use crate::permissions::Permissions;
+    use crate::remote::RemoteOptions;
+    use crate::run_js;
+    use crate::test_util::{ASSERT, run_script, serve, serve_redirect};
+
+    #[tokio::test]
+    async fn test_inspect() {
+        let source = [
+            ASSERT,
+            r#"
+            const { inspect } = runjs;
+            assertEquals(inspect("text"), "'text'");
+            assertEquals(inspect(undefined), "undefined");
+            assertEquals(inspect(function named() {}), "[Function: named]");
+            assertEquals(inspect([1, "two", null]), "[ 1, 'two', null ]");
+            assertEquals(inspect({ a: 1, "b-c": [] }), "{ a: 1, 'b-c': [] }");
+            assertEquals(inspect(new Map([["key", 1]])), "Map(1) { 'key' => 1 }");
+            assertEquals(inspect({ a: { b: { c: { d: 1 } } } }), "{ a: { b: { c: [Object] } } }");
+            assertEquals(inspect({ a: { b: 1 } }, { depth: 0 }), "{ a: [Object] }");
+            assertEquals(inspect(new Error("boom")).split("\n")[0], "Error: boom");
+
+            const cyclic = { name: "cyclic" };
+            cyclic.self = cyclic;
+            assertEquals(inspect(cyclic), "{ name: 'cyclic', self: [Circular] }");
+
+            const long = { first: "a".repeat(40), second: "b".repeat(40) };
+            assertEquals(inspect(long), `{\n  first: '${"a".repeat(40)}',\n  second: '${"b".repeat(40)}'\n}`);
+            assertEquals(inspect(1, { colors: true }), "\x1b[33m1\x1b[39m");
+            "#,
+        ]
+        .concat();
+
+        run_script("inspect.js", &source, Permissions::default())
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_format() {
+        let source = [
+            ASSERT,
+            r#"
+            const { format } = runjs;
+            assertEquals(format("Hello", "runjs!", 1, undefined), "Hello runjs! 1 undefined");
+            assertEquals(format("%s is %d years", "Bob", 42), "Bob is 42 years");
+            assertEquals(format("%i and %f", "42.9", "1.5"), "42 and 1.5");
+            assertEquals(format("%o", { a: [1] }), "{ a: [ 1 ] }");
+            assertEquals(format("%j", { a: 1 }), '{"a":1}');
+            assertEquals(format("%c%s%%", "color: red", "styled"), "styled%");
+            assertEquals(format("%s and %s", "one"), "one and %s");
+            assertEquals(format("extra", { a: 1 }, "args"), "extra { a: 1 } args");
+            "#,
+        ]
+        .concat();
+
+        run_script("format.js", &source, Permissions::default())
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_errors_point_at_the_original_source() {
+        let source = r#"interface Point {
+    x: number;
+    y: number;
+}
+
+function length(point: Point): number {
+    throw new Error("not implemented");
+}
+
+length({ x: 1, y: 2 });
+"#;
+
+        let error = run_script("source_map.ts", source, Permissions::default())
+            .await
+            .unwrap_err()
+            .to_string();
+
+        assert!(error.contains("not implemented"), "{error}");
+        assert!(error.contains("source_map.ts:7:11"), "{error}");
+        assert!(
+            error.contains("> 7 |     throw new Error(\"not implemented\");\n    |           ^"),
+            "{error}"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_syntax_errors_are_reported_with_their_location() {
+        let source = "const greeting: string = \"hello\";\nconst broken = (1 + ;\n";
+
+        let error = run_script("syntax_error.ts", source, Permissions::default())
+            .await
+            .unwrap_err()
+            .to_string();
+
+        assert!(error.contains("syntax_error.ts:2:"), "{error}");
+        assert!(error.contains("> 2 | const broken = (1 + ;"), "{error}");
+        assert!(error.contains("^"), "{error}");
+    }
+
+    #[tokio::test]
+    async fn test_unknown_media_type_is_a_load_error() {
+        let error = run_script("notes.txt", "just some text", Permissions::default())
+            .await
+            .unwrap_err()
+            .to_string();
+
+        assert!(error.contains("Unsupported module type"), "{error}");
+    }
+
+    #[tokio::test]
+    async fn test_remote_imports() {
+        let port = serve(|request| {
+            match request.path.as_str() {
+            "/math.ts" => (
+                "200 OK",
+                "application/typescript",
+                "export { double } from \"./double.js\";\nexport const add = (a: number, b: number): number => a + b;\n"
+                    .to_string(),
+            ),
+            "/double.js" => (
+                "200 OK",
+                "text/javascript",
+                "export const double = (n) => n * 2;\n".to_string(),
+            ),
+            _ => ("404 Not Found", "text/plain", "not found".to_string()),
+        }
+        });
+        let source = format!(
+            r#"{ASSERT}
+            import {{ add, double }} from "http://127.0.0.1:{port}/math.ts";
+            assertEquals(add(1, 2), 3);
+            assertEquals(double(21), 42);
+            "#
+        );
+
+        let (permissions, _) =
+            Permissions::from_args(vec![format!("--allow-net=127.0.0.1:{port}")]);
+        run_script("remote_imports.js", &source, permissions)
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_remote_imports_need_net_access() {
+        let port = serve(|_| {
+            (
+                "200 OK",
+                "text/javascript",
+                "export const answer = 42;\n".to_string(),
+            )
+        });
+        let source = format!(r#"import {{ answer }} from "http://127.0.0.1:{port}/answer.js";"#);
+
+        let error = run_script("remote_imports_denied.js", &source, Permissions::default())
+            .await
+            .unwrap_err();
+        assert!(
+            error
+                .to_string()
+                .contains(&format!("Requires net access to 127.0.0.1:{port}")),
+            "{error}"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_redirected_remote_imports() {
+        let port = serve(|request| match request.path.as_str() {
+            "/lib/math.js" => (
+                "200 OK",
+                "text/javascript",
+                "export { double } from \"./double.js\";\n".to_string(),
+            ),
+            "/lib/double.js" => (
+                "200 OK",
+                "text/javascript",
+                "export const double = (n) => n * 2;\n".to_string(),
+            ),
+            _ => ("404 Not Found", "text/plain", "not found".to_string()),
+        });
+        let redirect = serve_redirect(format!("http://127.0.0.1:{port}/lib/math.js"));
+        // `./double.js` is only found next to the module the redirect leads to.
+        let source = format!(
+            r#"{ASSERT}
+            import {{ double }} from "http://127.0.0.1:{redirect}/math.js";
+            assertEquals(double(21), 42);
+            "#
+        );
+
+        let (permissions, _) = Permissions::from_args(vec![format!(
+            "--allow-net=127.0.0.1:{redirect},127.0.0.1:{port}"
+        )]);
+        run_script("redirected_imports.js", &source, permissions)
+            .await
+            .unwrap();
+
+        let (permissions, _) =
+            Permissions::from_args(vec![format!("--allow-net=127.0.0.1:{redirect}")]);
+        let error = run_script("redirected_imports_denied.js", &source, permissions)
+            .await
+            .unwrap_err();
+        assert!(
+            error
+                .to_string()
+                .contains(&format!("Requires net access to 127.0.0.1:{port}")),
+            "{error}"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_bare_specifiers() {
+        let dir =
+            std::env::temp_dir().join(format!("runjs_{}_bare_specifiers", std::process::id()));
+        let write = |path: &str, contents: &str| {
+            let path = dir.join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, contents).unwrap();
+        };
+        write(
+            "vendor/greet.ts",
+            "export const greet = (name: string): string => `hello ${name}`;\n",
+        );
+        write(
+            "node_modules/shout/package.json",
+            r#"{ "module": "./index.mjs" }"#,
+        );
+        write(
+            "node_modules/shout/index.mjs",
+            "export const shout = (text) => text.toUpperCase();\n",
+        );
+        write(
+            "main.ts",
+            &format!(
+                r#"{ASSERT}
+                import {{ greet }} from "greet";
+                import {{ shout }} from "shout";
+                assertEquals(shout(greet("runjs")), "HELLO RUNJS");
+                "#
+            ),
+        );
+
+        let base = deno_core::ModuleSpecifier::from_file_path(dir.join("import_map.json")).unwrap();
+        let import_map =
+            ImportMap::from_json(&base, r#"{ "imports": { "greet": "./vendor/greet.ts" } }"#)
+                .unwrap();
+        let remote_options = RemoteOptions {
+            cache_dir: dir.join("cache"),
+            ..Default::default()
+        };
+        let result = run_js(
+            dir.join("main.ts").to_str().unwrap(),
+            Permissions::default(),
+            remote_options,
+            Some(import_map),
+        )
+        .await;
+        std::fs::remove_dir_all(&dir).unwrap();
+        result.unwrap();
     }
 }
@@ -0,0 +1,438 @@
+// Loads modules from disk, or over HTTP through `RemoteModules`, transpiling TypeScript and JSX to JavaScript.
+// Specifiers go through the import map first, if there is one, and bare specifiers it doesn't map are looked up in
+// `node_modules`.
+//
+// Transpiled modules carry an inline source map. The loader keeps the original source and the source map of every
+// module it loaded, so that deno_core can map stack traces back to the original lines, and so that errors can show
+// the original code around where they were thrown. Modules that fail to parse are reported the same way, with the
+// original code around the syntax error.
+
+// `ModuleLoaderError` is the error type of deno_core's `ModuleLoader` trait, the helpers below return it as is.
+#![allow(clippy::result_large_err)]
+
+use crate::import_map::ImportMap;
+use crate::node_modules;
+use crate::permissions::SharedPermissions;
+use crate::remote::RemoteModules;
+use base64::Engine;
+use deno_ast::EmitOptions;
+use deno_ast::MediaType;
+use deno_ast::ParseDiagnostic;
+use deno_ast::ParseParams;
+use deno_ast::SourceMapOption;
+use deno_ast::diagnostics::Diagnostic;
+use deno_core::ModuleLoadResponse;
+use deno_core::ModuleSourceCode;
+use deno_core::ModuleSpecifier;
+use deno_core::error::ModuleLoaderError;
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::path::Path;
+use std::rc::Rc;
+
+const SOURCE_MAP_PREFIX: &str = "//# sourceMappingURL=data:application/json;base64,";
+
+#[derive(Default)]
+struct LoadedModules {
+    // Keyed by module specifier.
+    sources: HashMap<String, String>,
+    source_maps: HashMap<String, Vec<u8>>,
+}
+
+pub struct TsModuleLoader {
+    // Shared with the futures loading remote modules.
+    modules: Rc<RefCell<LoadedModules>>,
+    remote: Rc<RemoteModules>,
+    import_map: Option<ImportMap>,
+    // Remote modules need net access to their host.
+    permissions: SharedPermissions,
+}
+
+impl TsModuleLoader {
+    pub fn new(
+        remote: RemoteModules,
+        import_map: Option<ImportMap>,
+        permissions: SharedPermissions,
+    ) -> Self {
+        Self {
+            modules: Default::default(),
+            remote: Rc::new(remote),
+            import_map,
+            permissions,
+        }
+    }
+
+    /// Returns the original code around the location of the first stack frame in `error` that points into a loaded
+    /// module, with a caret under the column.
+    pub fn code_frame_for(&self, error: &str) -> Option<String> {
+        let modules = self.modules.borrow();
+        error.lines().find_map(|line| {
+            let location = line.trim().strip_prefix("at ")?;
+            // Frames are either `at file:///main.ts:3:7` or `at name (file:///main.ts:3:7)`.
+            let location = match location.rsplit_once(" (") {
+                Some((_, location)) => location.strip_suffix(')')?,
+                None => location,
+            };
+            let mut parts = location.rsplitn(3, ':');
+            let column = parts.next()?.parse().ok()?;
+            let line = parts.next()?.parse().ok()?;
+            let source = modules.sources.get(parts.next()?)?;
+            Some(code_frame(source, line, column))
+        })
+    }
+
+    fn load_file(
+        &self,
+        module_specifier: &ModuleSpecifier,
+    ) -> Result<deno_core::ModuleSource, ModuleLoaderError> {
+        let path = module_specifier
+            .to_file_path()
+            .map_err(|()| load_error(format!("Unsupported module specifier {module_specifier}")))?;
+        let source = std::fs::read_to_string(&path)?;
+        compile(
+            &self.modules,
+            module_specifier,
+            module_specifier,
+            MediaType::from_path(&path),
+            source,
+        )
+    }
+}
+
+impl deno_core::ModuleLoader for TsModuleLoader {
+    fn resolve(
+        &self,
+        specifier: &str,
+        referrer: &str,
+        _kind: deno_core::ResolutionKind,
+    ) -> Result<deno_core::ModuleSpecifier, deno_core::error::ModuleLoaderError> {
+        // The main module is resolved against a referrer that isn't a URL.
+        let Ok(referrer_url) = ModuleSpecifier::parse(referrer) else {
+            return deno_core::resolve_import(specifier, referrer).map_err(|e| e.into());
+        };
+
+        if let Some(import_map) = &self.import_map
+            && let Some(url) = import_map
+                .resolve(specifier, &referrer_url)
+                .map_err(|err| load_error(err.to_string()))?
+        {
+            return Ok(url);
+        }
+        deno_core::resolve_import(specifier, referrer).or_else(|err| {
+            node_modules::resolve(specifier, &referrer_url).ok_or_else(|| err.into())
+        })
+    }
+
+    fn load(
+        &self,
+        module_specifier: &deno_core::ModuleSpecifier,
+        _maybe_referrer: Option<&deno_core::ModuleSpecifier>,
+        _is_dyn_import: bool,
+        _requested_module_type: deno_core::RequestedModuleType,
+    ) -> ModuleLoadResponse {
+        if !matches!(module_specifier.scheme(), "http" | "https") {
+            return ModuleLoadResponse::Sync(self.load_file(module_specifier));
+        }
+        if let Err(err) = self.permissions.lock().unwrap().check_net(module_specifier) {
+            return ModuleLoadResponse::Sync(Err(load_error(err.to_string())));
+        }
+
+        let module_specifier = module_specifier.clone();
+        let modules = self.modules.clone();
+        let remote = self.remote.clone();
+        let permissions = self.permissions.clone();
+        ModuleLoadResponse::Async(Box::pin(async move {
+            let module = remote
+                .load(&module_specifier)
+                .await
+                .map_err(|err| load_error(err.to_string()))?;
+            // Downloads check every redirect, but a module from the cache may have been redirected when other hosts
+            // were allowed.
+            if module.url != module_specifier {
+                permissions
+                    .lock()
+                    .unwrap()
+                    .check_net(&module.url)
+                    .map_err(|err| load_error(err.to_string()))?;
+            }
+            // The module is registered under the URL it was found at, for its relative imports to resolve against it.
+            let media_type = remote_media_type(&module.url, module.content_type.as_deref());
+            compile(
+                &modules,
+                &module_specifier,
+                &module.url,
+                media_type,
+                module.source,
+            )
+        }))
+    }
+
+    fn get_source_map(&self, file_name: &str) -> Option<Cow<'_, [u8]>> {
+        let source_map = self.modules.borrow().source_maps.get(file_name)?.clone();
+        Some(Cow::Owned(source_map))
+    }
+
+    fn get_source_mapped_source_line(&self, file_name: &str, line_number: usize) -> Option<String> {
+        let modules = self.modules.borrow();
+        let line = modules.sources.get(file_name)?.lines().nth(line_number)?;
+        Some(line.to_string())
+    }
+}
+
+// Transpiles the module if needed, and keeps its source and source map around for error reporting. `found_specifier`
+// is where `module_specifier` redirected to, or the same specifier.
+fn compile(
+    modules: &RefCell<LoadedModules>,
+    module_specifier: &ModuleSpecifier,
+    found_specifier: &ModuleSpecifier,
+    media_type: MediaType,
+    source: String,
+) -> Result<deno_core::ModuleSource, ModuleLoaderError> {
+    let (module_type, should_transpile) = match media_type {
+        MediaType::JavaScript | MediaType::Mjs | MediaType::Cjs => {
+            (deno_core::ModuleType::JavaScript, false)
+        }
+        MediaType::Jsx => (deno_core::ModuleType::JavaScript, true),
+        MediaType::TypeScript
+        | MediaType::Cts
+        | MediaType::Dts
+        | MediaType::Dmts
+        | MediaType::Dcts
+        | MediaType::Tsx => (deno_core::ModuleType::JavaScript, true),
+        MediaType::Json => (deno_core::ModuleType::Json, false),
+        _ => {
+            return Err(load_error(format!(
+                "Unsupported module type of {found_specifier}, expected JavaScript, TypeScript or JSON"
+            )));
+        }
+    };
+
+    let code = if should_transpile {
+        let parsed = deno_ast::parse_module(ParseParams {
+            specifier: found_specifier.clone(),
+            text: source.as_str().into(),
+            media_type,
+            capture_tokens: false,
+            scope_analysis: false,
+            maybe_syntax: None,
+        })
+        .map_err(|diagnostic| parse_error(&diagnostic, &source))?;
+        let code = parsed
+            .transpile(
+                &Default::default(),
+                &Default::default(),
+                &EmitOptions {
+                    source_map: SourceMapOption::Inline,
+                    inline_sources: true,
+                    ..Default::default()
+                },
+            )
+            .map_err(|err| load_error(format!("Failed to transpile {found_specifier}: {err}")))?
+            .into_source()
+            .text;
+        if let Some(source_map) = inline_source_map(&code) {
+            modules
+                .borrow_mut()
+                .source_maps
+                .insert(found_specifier.to_string(), source_map);
+        }
+        code.into_bytes()
+    } else {
+        source.clone().into_bytes()
+    };
+    // Only modules that loaded are kept, the code frame of a syntax error is already part of its message.
+    modules
+        .borrow_mut()
+        .sources
+        .insert(found_specifier.to_string(), source);
+
+    Ok(deno_core::ModuleSource::new_with_redirect(
+        module_type,
+        ModuleSourceCode::Bytes(code.into_boxed_slice().into()),
+        module_specifier,
+        found_specifier,
+        None,
+    ))
+}
+
+// Servers don't agree on the content type of TypeScript, so anything that isn't clearly JavaScript, TypeScript or
+// JSON falls back to the extension in the URL.
+fn remote_media_type(url: &ModuleSpecifier, content_type: Option<&str>) -> MediaType {
+    let from_extension = MediaType::from_path(Path::new(url.path()));
+    let essence = content_type
+        .and_then(|content_type| content_type.split(';').next())
+        .map(|essence| essence.trim().to_ascii_lowercase());
+
+    match essence.as_deref() {
+        Some(
+            "application/typescript"
+            | "application/x-typescript"
+            | "text/typescript"
+            | "video/mp2t"
+            | "video/vnd.dlna.mpeg-tts",
+        ) => match from_extension {
+            MediaType::Tsx => MediaType::Tsx,
+            _ => MediaType::TypeScript,
+        },
+        Some(
+            "application/javascript"
+            | "application/x-javascript"
+            | "application/ecmascript"
+            | "text/javascript"
+            | "text/ecmascript",
+        ) => match from_extension {
+            MediaType::Jsx => MediaType::Jsx,
+            _ => MediaType::JavaScript,
+        },
+        Some("text/jsx") => MediaType::Jsx,
+        Some("text/tsx") => MediaType::Tsx,
+        Some("application/json" | "text/json") => MediaType::Json,
+        _ => from_extension,
+    }
+}
+
+// deno_core only shows the message of a load error, so the diagnostics are carried in a plain error.
+fn load_error(message: String) -> ModuleLoaderError {
+    std::io::Error::other(message).into()
+}
+
+fn parse_error(diagnostic: &ParseDiagnostic, source: &str) -> ModuleLoaderError {
+    let position = diagnostic.display_position();
+    load_error(format_diagnostic(
+        &diagnostic.message(),
+        diagnostic.specifier.as_str(),
+        source,
+        position.line_number,
+        position.column_number,
+    ))
+}
+
+/// Formats a diagnostic as the message, the location in `file:line:column` form and a code frame.
+fn format_diagnostic(
+    message: &str,
+    specifier: &str,
+    source: &str,
+    line: usize,
+    column: usize,
+) -> String {
+    let frame = code_frame(source, line, column);
+    format!("{message}\n    at {specifier}:{line}:{column}\n\n{frame}")
+}
+
+// Decodes the source map that deno_ast appends as a data URL to the end of the transpiled code.
+fn inline_source_map(code: &str) -> Option<Vec<u8>> {
+    let (_, encoded) = code.rsplit_once(SOURCE_MAP_PREFIX)?;
+    base64::engine::general_purpose::STANDARD
+        .decode(encoded.trim_end())
+        .ok()
+}
+
+/// Shows the line at `line` and `column`, both starting at 1, with the two lines before it and a caret under the
+/// column.
+pub fn code_frame(source: &str, line: usize, column: usize) -> String {
+    let lines: Vec<&str> = source.lines().collect();
+    let line = line.clamp(1, lines.len().max(1));
+    let first = line.saturating_sub(2).max(1);
+    let width = line.to_string().len();
+
+    let mut frame = String::new();
+    for number in first..=line {
+        let text = lines.get(number - 1).copied().unwrap_or_default();
+        let marker = if number == line { '>' } else { ' ' };
+        frame.push_str(&format!("{marker} {number:>width$} | {text}\n"));
+    }
+
+    // Tabs keep their width, so the caret lines up with the code above it.
+    let text = lines.get(line - 1).copied().unwrap_or_default();
+    let padding: String = text
+        .chars()
+        .take(column.saturating_sub(1))
+        .map(|c| if c == '\t' { '\t' } else { ' ' })
+        .collect();
+    frame.push_str(&format!("  {:width$} | {padding}^", ""));
+    frame
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_code_frame() {
+        let source = "const a = 1;\nconst b = 2;\n\tthrow new Error(\"boom\");\nconst c = 3;\n";
+
+        assert_eq!(
+            code_frame(source, 3, 8),
+            "  1 | const a = 1;\n  2 | const b = 2;\n> 3 | \tthrow new Error(\"boom\");\n    | \t      ^"
+        );
+        assert_eq!(code_frame(source, 1, 1), "> 1 | const a = 1;\n    | ^");
+    }
+
+    #[test]
+    fn test_code_frame_for_stack_trace() {
+        let loader = TsModuleLoader::new(
+            RemoteModules::new(Default::default(), Default::default()).unwrap(),
+            None,
+            Default::default(),
+        );
+        loader.modules.borrow_mut().sources.insert(
+            "file:///app/main.ts".to_string(),
+            "const x: number = 1;\nfunction fail(): never {\n  throw new Error(\"boom\");\n}\n"
+                .to_string(),
+        );
+
+        let error = "Uncaught Error: boom\n    at fail (file:///app/main.ts:3:9)\n    at file:///app/main.ts:5:1";
+        assert_eq!(
+            loader.code_frame_for(error).unwrap(),
+            "  1 | const x: number = 1;\n  2 | function fail(): never {\n> 3 |   throw new Error(\"boom\");\n    |         ^"
+        );
+        assert!(
+            loader
+                .code_frame_for("Uncaught Error: boom\n    at ext:runjs/runtime.js:1:1")
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn test_format_diagnostic() {
+        let source = "const a = 1;\nconst b c = 2;\n";
+
+        assert_eq!(
+            format_diagnostic("Expected ';', got 'c'", "file:///app/main.ts", source, 2, 9),
+            "Expected ';', got 'c'\n    at file:///app/main.ts:2:9\n\n  1 | const a = 1;\n> 2 | const b c = 2;\n    |         ^"
+        );
+    }
+
+    #[test]
+    fn test_remote_media_type() {
+        let url = |path| ModuleSpecifier::parse(&format!("https://example.com{path}")).unwrap();
+
+        assert_eq!(
+            remote_media_type(&url("/mod"), Some("application/typescript; charset=utf-8")),
+            MediaType::TypeScript
+        );
+        assert_eq!(
+            remote_media_type(&url("/app.tsx"), Some("video/mp2t")),
+            MediaType::Tsx
+        );
+        assert_eq!(
+            remote_media_type(&url("/mod.ts"), Some("text/javascript")),
+            MediaType::JavaScript
+        );
+        assert_eq!(
+            remote_media_type(&url("/mod.ts"), Some("text/plain")),
+            MediaType::TypeScript
+        );
+        assert_eq!(remote_media_type(&url("/data.json"), None), MediaType::Json);
+        assert_eq!(remote_media_type(&url("/readme"), None), MediaType::Unknown);
+    }
+
+    #[test]
+    fn test_inline_source_map() {
+        let code = "console.log(1);\n//# sourceMappingURL=data:application/json;base64,eyJ2ZXJzaW9uIjozfQ==\n";
+
+        assert_eq!(inline_source_map(code).unwrap(), br#"{"version":3}"#);
+        assert!(inline_source_map("console.log(1);").is_none());
+    }
+}
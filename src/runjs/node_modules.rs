@@ -0,0 +1,169 @@
+// Resolves bare specifiers that the import map doesn't cover, like `preact` or `preact/hooks`, to a package in a
+// `node_modules` directory the way Node does: looking next to the importing module first and then in each directory
+// above it. Packages have to ship ES modules, there's no CommonJS support.
+
+use deno_core::ModuleSpecifier;
+use serde_json::Value;
+use std::path::{Path, PathBuf};
+
+const EXTENSIONS: [&str; 4] = ["js", "mjs", "ts", "json"];
+
+/// Returns the file `specifier` names in the closest `node_modules` directory above `referrer`, if there is one.
+pub fn resolve(specifier: &str, referrer: &ModuleSpecifier) -> Option<ModuleSpecifier> {
+    let (name, subpath) = split_package_name(specifier)?;
+    let referrer = referrer.to_file_path().ok()?;
+
+    let mut dir = referrer.parent()?;
+    loop {
+        let package_dir = dir.join("node_modules").join(name);
+        if package_dir.is_dir() {
+            let path = resolve_in_package(&package_dir, subpath)?;
+            return ModuleSpecifier::from_file_path(path).ok();
+        }
+        dir = dir.parent()?;
+    }
+}
+
+// `@scope/name/sub/path` is the package `@scope/name` and the subpath `sub/path`.
+fn split_package_name(specifier: &str) -> Option<(&str, Option<&str>)> {
+    if specifier.is_empty() || specifier.starts_with('.') || specifier.contains(':') {
+        return None;
+    }
+    let name_end = if specifier.starts_with('@') {
+        let scope_end = specifier.find('/')?;
+        specifier[scope_end + 1..]
+            .find('/')
+            .map(|end| scope_end + 1 + end)
+    } else {
+        specifier.find('/')
+    };
+    match name_end {
+        Some(end) => Some((&specifier[..end], Some(&specifier[end + 1..]))),
+        None => Some((specifier, None)),
+    }
+}
+
+fn resolve_in_package(package_dir: &Path, subpath: Option<&str>) -> Option<PathBuf> {
+    let manifest = std::fs::read_to_string(package_dir.join("package.json"))
+        .ok()
+        .and_then(|manifest| serde_json::from_str::<Value>(&manifest).ok())
+        .unwrap_or(Value::Null);
+
+    let export = match subpath {
+        Some(subpath) => manifest["exports"].get(format!("./{subpath}")),
+        // `exports` is either the entry point itself, or has it under `.`, or is conditions for it.
+        None => match &manifest["exports"] {
+            Value::Object(exports) if exports.contains_key(".") => exports.get("."),
+            Value::Null => None,
+            exports => Some(exports),
+        },
+    };
+    if let Some(target) = export.and_then(export_target) {
+        return resolve_file(&package_dir.join(target));
+    }
+
+    match subpath {
+        Some(subpath) => resolve_file(&package_dir.join(subpath)),
+        None => ["module", "main"]
+            .iter()
+            .find_map(|field| manifest[field].as_str())
+            .and_then(|entry| resolve_file(&package_dir.join(entry)))
+            .or_else(|| resolve_file(&package_dir.join("index"))),
+    }
+}
+
+// An export is a path, or conditions mapping to one, of which only `import` and `default` apply to ES modules.
+fn export_target(export: &Value) -> Option<&str> {
+    match export {
+        Value::String(target) => Some(target),
+        Value::Object(conditions) => ["import", "default"]
+            .iter()
+            .find_map(|condition| conditions.get(*condition).and_then(export_target)),
+        _ => None,
+    }
+}
+
+// Tries the path as it is, then with each of the extensions, then as a directory with an index file.
+fn resolve_file(path: &Path) -> Option<PathBuf> {
+    if path.is_file() {
+        return Some(path.to_path_buf());
+    }
+    let file_name = path.file_name()?.to_str()?;
+    EXTENSIONS
+        .iter()
+        .map(|extension| path.with_file_name(format!("{file_name}.{extension}")))
+        .chain(
+            EXTENSIONS
+                .iter()
+                .map(|extension| path.join(format!("index.{extension}"))),
+        )
+        .find(|candidate| candidate.is_file())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_package_name() {
+        assert_eq!(split_package_name("preact"), Some(("preact", None)));
+        assert_eq!(
+            split_package_name("preact/hooks"),
+            Some(("preact", Some("hooks")))
+        );
+        assert_eq!(
+            split_package_name("@scope/name/sub/path.js"),
+            Some(("@scope/name", Some("sub/path.js")))
+        );
+        assert_eq!(
+            split_package_name("@scope/name"),
+            Some(("@scope/name", None))
+        );
+        assert_eq!(split_package_name("./local.js"), None);
+        assert_eq!(split_package_name("node:fs"), None);
+    }
+
+    #[test]
+    fn test_resolve() {
+        let root = std::env::temp_dir().join(format!("runjs_{}_node_modules", std::process::id()));
+        let modules = root.join("node_modules");
+        let write = |path: &str, contents: &str| {
+            let path = modules.join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, contents).unwrap();
+        };
+        write("plain/index.js", "");
+        write("main/package.json", r#"{ "main": "./lib/main" }"#);
+        write("main/lib/main.js", "");
+        write("main/lib/extra.js", "");
+        write(
+            "@scope/exports/package.json",
+            r#"{ "main": "./cjs.js", "exports": { ".": { "require": "./cjs.js", "import": "./esm.mjs" }, "./feature": "./src/feature.js" } }"#,
+        );
+        write("@scope/exports/esm.mjs", "");
+        write("@scope/exports/src/feature.js", "");
+
+        std::fs::create_dir_all(root.join("src/nested")).unwrap();
+        let referrer = ModuleSpecifier::from_file_path(root.join("src/nested/main.ts")).unwrap();
+        let resolve =
+            |specifier| resolve(specifier, &referrer).map(|url| url.to_file_path().unwrap());
+
+        assert_eq!(resolve("plain"), Some(modules.join("plain/index.js")));
+        assert_eq!(resolve("main"), Some(modules.join("main/lib/main.js")));
+        assert_eq!(
+            resolve("main/lib/extra"),
+            Some(modules.join("main/lib/extra.js"))
+        );
+        assert_eq!(
+            resolve("@scope/exports"),
+            Some(modules.join("@scope/exports/esm.mjs"))
+        );
+        assert_eq!(
+            resolve("@scope/exports/feature"),
+            Some(modules.join("@scope/exports/src/feature.js"))
+        );
+        assert_eq!(resolve("missing"), None);
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,324 @@
+// Deno-style permissions for the ops that reach outside of the runtime.
+//
+// Scripts start without any access. The `--allow-read`, `--allow-write` and `--allow-net` flags grant access to
+// everything, or, with a value, to a comma separated list of paths or hosts. With `--prompt`, an op asking for
+// access it wasn't granted asks on the terminal instead of failing right away.
+
+use std::io::{BufRead, IsTerminal, Write};
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+#[derive(Debug, Clone, thiserror::Error, deno_error::JsError)]
+#[class("PermissionDenied")]
+#[error("Requires {kind} access to {target}, run again with the --allow-{kind} flag")]
+pub struct PermissionDenied {
+    kind: &'static str,
+    target: String,
+}
+
+/// Access granted for one kind of resource.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Grant<T> {
+    All,
+    Only(Vec<T>),
+}
+
+impl<T> Default for Grant<T> {
+    fn default() -> Self {
+        Grant::Only(Vec::new())
+    }
+}
+
+/// A host, optionally restricted to a port.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct NetAddress {
+    host: String,
+    port: Option<u16>,
+}
+
+impl NetAddress {
+    fn parse(address: &str) -> Self {
+        match address.rsplit_once(':') {
+            Some((host, port)) if port.parse::<u16>().is_ok() => Self {
+                host: host.to_string(),
+                port: port.parse().ok(),
+            },
+            _ => Self {
+                host: address.to_string(),
+                port: None,
+            },
+        }
+    }
+
+    fn allows(&self, other: &NetAddress) -> bool {
+        self.host == other.host && (self.port.is_none() || self.port == other.port)
+    }
+}
+
+/// The permissions of a runtime, shared by its ops and its module loader, so that access granted at a prompt applies
+/// to all of them.
+pub type SharedPermissions = Arc<Mutex<Permissions>>;
+
+#[derive(Debug, Default, Clone)]
+pub struct Permissions {
+    read: Grant<PathBuf>,
+    write: Grant<PathBuf>,
+    net: Grant<NetAddress>,
+    prompt: bool,
+}
+
+impl Permissions {
+    /// Reads the permission flags out of the arguments, returning the arguments that aren't permission flags. Only
+    /// the flags before the first positional argument are read, the ones after it belong to the script.
+    pub fn from_args(args: Vec<String>) -> (Self, Vec<String>) {
+        let cwd = std::env::current_dir().unwrap_or_default();
+        let mut permissions = Permissions::default();
+        let mut rest = Vec::new();
+
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            if !arg.starts_with('-') {
+                rest.push(arg);
+                rest.extend(args);
+                break;
+            }
+            let (flag, value) = match arg.split_once('=') {
+                Some((flag, value)) => (flag, Some(value)),
+                None => (arg.as_str(), None),
+            };
+            let list = || {
+                value
+                    .into_iter()
+                    .flat_map(|v| v.split(','))
+                    .filter(|v| !v.is_empty())
+            };
+            match flag {
+                "--allow-read" => {
+                    permissions.read = grant(value, list().map(|p| resolve_path(&cwd, p)))
+                }
+                "--allow-write" => {
+                    permissions.write = grant(value, list().map(|p| resolve_path(&cwd, p)))
+                }
+                "--allow-net" => permissions.net = grant(value, list().map(NetAddress::parse)),
+                "-A" | "--allow-all" => {
+                    permissions.read = Grant::All;
+                    permissions.write = Grant::All;
+                    permissions.net = Grant::All;
+                }
+                "--prompt" => permissions.prompt = true,
+                _ => rest.push(arg),
+            }
+        }
+        (permissions, rest)
+    }
+
+    /// Checks that the path can be read, returning it resolved against the current directory.
+    pub fn check_read(&mut self, path: &str) -> Result<PathBuf, PermissionDenied> {
+        check_path(&mut self.read, self.prompt, "read", path)
+    }
+
+    /// Checks that the path can be written or removed, returning it resolved against the current directory.
+    pub fn check_write(&mut self, path: &str) -> Result<PathBuf, PermissionDenied> {
+        check_path(&mut self.write, self.prompt, "write", path)
+    }
+
+    /// Checks that the host of the URL can be reached.
+    pub fn check_net(&mut self, url: &reqwest::Url) -> Result<(), PermissionDenied> {
+        let address = NetAddress {
+            host: url.host_str().unwrap_or_default().to_string(),
+            port: url.port_or_known_default(),
+        };
+        let target = match address.port {
+            Some(port) => format!("{}:{port}", address.host),
+            None => address.host.clone(),
+        };
+        check(
+            &mut self.net,
+            self.prompt,
+            "net",
+            &target,
+            |granted| granted.allows(&address),
+            || address.clone(),
+        )
+    }
+}
+
+fn grant<T>(value: Option<&str>, list: impl Iterator<Item = T>) -> Grant<T> {
+    match value {
+        None => Grant::All,
+        Some(_) => Grant::Only(list.collect()),
+    }
+}
+
+fn check_path(
+    grant: &mut Grant<PathBuf>,
+    prompt: bool,
+    kind: &'static str,
+    path: &str,
+) -> Result<PathBuf, PermissionDenied> {
+    let path = resolve_path(&std::env::current_dir().unwrap_or_default(), path);
+    let target = path.display().to_string();
+    check(
+        grant,
+        prompt,
+        kind,
+        &target,
+        |granted| path.starts_with(granted),
+        || path.clone(),
+    )?;
+    Ok(path)
+}
+
+fn check<T>(
+    grant: &mut Grant<T>,
+    prompt: bool,
+    kind: &'static str,
+    target: &str,
+    is_allowed: impl Fn(&T) -> bool,
+    to_grant: impl FnOnce() -> T,
+) -> Result<(), PermissionDenied> {
+    let granted = match grant {
+        Grant::All => return Ok(()),
+        Grant::Only(granted) => granted,
+    };
+    if granted.iter().any(is_allowed) {
+        return Ok(());
+    }
+
+    let denied = PermissionDenied {
+        kind,
+        target: target.to_string(),
+    };
+    if !prompt || !std::io::stdin().is_terminal() {
+        return Err(denied);
+    }
+    match ask(kind, target) {
+        Answer::Yes => granted.push(to_grant()),
+        Answer::All => *grant = Grant::All,
+        Answer::No => return Err(denied),
+    }
+    Ok(())
+}
+
+enum Answer {
+    Yes,
+    No,
+    All,
+}
+
+fn ask(kind: &str, target: &str) -> Answer {
+    let mut stderr = std::io::stderr();
+    let mut line = String::new();
+    loop {
+        let _ = write!(
+            stderr,
+            "runjs requests {kind} access to \"{target}\". Allow? [y/n/A] (y = yes, n = no, A = allow all {kind}) "
+        );
+        let _ = stderr.flush();
+
+        line.clear();
+        if std::io::stdin().lock().read_line(&mut line).unwrap_or(0) == 0 {
+            return Answer::No;
+        }
+        match line.trim() {
+            "y" | "Y" => return Answer::Yes,
+            "n" | "N" => return Answer::No,
+            "A" => return Answer::All,
+            _ => {}
+        }
+    }
+}
+
+// Makes the path absolute and removes `.` and `..` from it. This is done on the path itself instead of asking the
+// file system, since the file doesn't have to exist yet.
+fn resolve_path(cwd: &Path, path: &str) -> PathBuf {
+    let mut resolved = PathBuf::new();
+    for component in cwd.join(path).components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                resolved.pop();
+            }
+            component => resolved.push(component),
+        }
+    }
+    resolved
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn permissions(args: &[&str]) -> Permissions {
+        let (permissions, rest) =
+            Permissions::from_args(args.iter().map(|a| a.to_string()).collect());
+        assert_eq!(rest, vec!["main.ts"]);
+        permissions
+    }
+
+    #[test]
+    fn test_nothing_is_allowed_by_default() {
+        let mut permissions = permissions(&["main.ts"]);
+
+        assert!(permissions.check_read("log.txt").is_err());
+        assert!(permissions.check_write("/tmp/log.txt").is_err());
+        let url = reqwest::Url::parse("https://example.com").unwrap();
+        assert!(permissions.check_net(&url).is_err());
+    }
+
+    #[test]
+    fn test_allow_paths() {
+        let mut permissions =
+            permissions(&["--allow-read=/data,/etc/hosts", "--allow-write", "main.ts"]);
+
+        assert!(permissions.check_read("/data/log.txt").is_ok());
+        assert!(permissions.check_read("/etc/hosts").is_ok());
+        assert!(permissions.check_read("/etc/passwd").is_err());
+        assert!(permissions.check_read("/data/../etc/passwd").is_err());
+        assert!(permissions.check_read("/database").is_err());
+        assert!(permissions.check_write("/anywhere").is_ok());
+        assert_eq!(
+            permissions.check_read("/data/./logs/../log.txt").unwrap(),
+            PathBuf::from("/data/log.txt")
+        );
+    }
+
+    #[test]
+    fn test_allow_hosts() {
+        let mut permissions = permissions(&["--allow-net=example.com,localhost:8080", "main.ts"]);
+        let check = |permissions: &mut Permissions, url| {
+            permissions.check_net(&reqwest::Url::parse(url).unwrap())
+        };
+
+        assert!(check(&mut permissions, "https://example.com/index.html").is_ok());
+        assert!(check(&mut permissions, "http://example.com:3000").is_ok());
+        assert!(check(&mut permissions, "http://localhost:8080/api").is_ok());
+        assert!(check(&mut permissions, "http://localhost:9000/api").is_err());
+        assert!(check(&mut permissions, "https://example.org").is_err());
+    }
+
+    #[test]
+    fn test_flags_after_the_script_are_left_alone() {
+        let (mut permissions, rest) = Permissions::from_args(
+            ["--allow-read=/data", "main.ts", "--allow-write", "-A"]
+                .iter()
+                .map(|a| a.to_string())
+                .collect(),
+        );
+
+        assert_eq!(rest, vec!["main.ts", "--allow-write", "-A"]);
+        assert!(permissions.check_read("/data/log.txt").is_ok());
+        assert!(permissions.check_write("/data/log.txt").is_err());
+    }
+
+    #[test]
+    fn test_denied_message() {
+        let mut permissions = permissions(&["main.ts"]);
+        let error = permissions.check_write("/tmp/log.txt").unwrap_err();
+
+        assert_eq!(
+            error.to_string(),
+            "Requires write access to /tmp/log.txt, run again with the --allow-write flag"
+        );
+    }
+}
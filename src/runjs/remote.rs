@@ -0,0 +1,570 @@
+// Fetches `http:` and `https:` modules, caching them on disk and checking them against a lockfile.
+//
+// The cache is content addressed. The body of a module is stored under its SHA-256 hash in `<cache>/blobs`, and
+// `<cache>/urls` maps the hash of every URL to the hash of its body and the content type it was served with. The
+// lockfile records the integrity hash of every URL imported so far, so a module that changed on the server fails to
+// load instead of running code nobody looked at.
+
+use crate::fetch;
+use crate::permissions::{PermissionDenied, SharedPermissions};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+pub const DEFAULT_LOCKFILE: &str = "runjs.lock";
+
+#[derive(Debug, Error)]
+pub enum RemoteError {
+    #[error("{url} is not in the cache, run again without --cached-only to download it")]
+    NotCached { url: String },
+
+    #[error("Failed to fetch {url}: {reason}")]
+    Fetch { url: String, reason: String },
+
+    #[error(
+        "Integrity check failed for {url}\n  lockfile: {expected}\n  actual:   {actual}\nRemove the entry from {lockfile} if the change is expected"
+    )]
+    Integrity {
+        url: String,
+        expected: String,
+        actual: String,
+        lockfile: String,
+    },
+
+    #[error(transparent)]
+    Permission(#[from] PermissionDenied),
+
+    #[error("Invalid lockfile {path}: {reason}")]
+    Lockfile { path: String, reason: String },
+
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+}
+
+#[derive(Debug, Clone)]
+pub struct RemoteOptions {
+    pub cache_dir: PathBuf,
+    pub lockfile: Option<PathBuf>,
+    /// Downloads every remote module again instead of using the cache.
+    pub reload: bool,
+    /// Fails instead of downloading modules that aren't in the cache.
+    pub cached_only: bool,
+}
+
+impl Default for RemoteOptions {
+    fn default() -> Self {
+        Self {
+            cache_dir: default_cache_dir(),
+            lockfile: None,
+            reload: false,
+            cached_only: false,
+        }
+    }
+}
+
+impl RemoteOptions {
+    /// Reads the remote module flags out of the arguments, returning the arguments that aren't remote module flags.
+    /// Unless `--no-lock` is passed, the lockfile is `runjs.lock` in the current directory. Like the permission flags,
+    /// only the flags before the first positional argument are read.
+    pub fn from_args(args: Vec<String>) -> (Self, Vec<String>) {
+        let mut options = RemoteOptions {
+            lockfile: Some(PathBuf::from(DEFAULT_LOCKFILE)),
+            ..Default::default()
+        };
+        let mut rest = Vec::new();
+
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            if !arg.starts_with('-') {
+                rest.push(arg);
+                rest.extend(args);
+                break;
+            }
+            match arg.split_once('=') {
+                Some(("--lock", path)) => options.lockfile = Some(PathBuf::from(path)),
+                _ => match arg.as_str() {
+                    "--no-lock" => options.lockfile = None,
+                    "--reload" => options.reload = true,
+                    "--cached-only" => options.cached_only = true,
+                    _ => rest.push(arg),
+                },
+            }
+        }
+        (options, rest)
+    }
+}
+
+// `$RUNJS_DIR`, or `runjs` in the user's cache directory.
+fn default_cache_dir() -> PathBuf {
+    if let Some(dir) = std::env::var_os("RUNJS_DIR") {
+        return PathBuf::from(dir);
+    }
+    let cache_home = std::env::var_os("XDG_CACHE_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".cache")))
+        .unwrap_or_else(std::env::temp_dir);
+    cache_home.join("runjs")
+}
+
+/// A remote module, as text.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RemoteModule {
+    /// The URL the module came from, after following redirects.
+    pub url: reqwest::Url,
+    pub content_type: Option<String>,
+    pub source: String,
+}
+
+// The body of a module, before it's checked against the lockfile.
+struct Download {
+    url: reqwest::Url,
+    content_type: Option<String>,
+    body: Vec<u8>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CacheEntry {
+    url: String,
+    // Where the URL redirected to, if it did.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    redirected_to: Option<String>,
+    hash: String,
+    content_type: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Lockfile {
+    version: String,
+    remote: BTreeMap<String, String>,
+}
+
+impl Default for Lockfile {
+    fn default() -> Self {
+        Self {
+            version: "1".to_string(),
+            remote: BTreeMap::new(),
+        }
+    }
+}
+
+pub struct RemoteModules {
+    client: reqwest::Client,
+    options: RemoteOptions,
+    lockfile: RefCell<Lockfile>,
+}
+
+impl RemoteModules {
+    /// Redirects are checked against `permissions` as they're followed, the URLs passed to `load` have to be checked
+    /// beforehand.
+    pub fn new(
+        options: RemoteOptions,
+        permissions: SharedPermissions,
+    ) -> Result<Self, RemoteError> {
+        let lockfile = match &options.lockfile {
+            Some(path) if path.exists() => {
+                let contents = std::fs::read_to_string(path)?;
+                serde_json::from_str(&contents).map_err(|e| RemoteError::Lockfile {
+                    path: path.display().to_string(),
+                    reason: e.to_string(),
+                })?
+            }
+            _ => Lockfile::default(),
+        };
+
+        Ok(Self {
+            client: fetch::client(permissions),
+            options,
+            lockfile: RefCell::new(lockfile),
+        })
+    }
+
+    /// Returns the module at `url`, from the cache unless reloading, and checks it against the lockfile.
+    pub async fn load(&self, url: &reqwest::Url) -> Result<RemoteModule, RemoteError> {
+        let cached = if self.options.reload {
+            None
+        } else {
+            self.read_cache(url)
+        };
+        let download = match cached {
+            Some(cached) => cached,
+            None if self.options.cached_only => {
+                return Err(RemoteError::NotCached {
+                    url: url.to_string(),
+                });
+            }
+            None => {
+                let download = self.fetch(url).await?;
+                self.write_cache(url, &download)?;
+                download
+            }
+        };
+
+        self.check_integrity(url, &download.body)?;
+        let source = String::from_utf8(download.body).map_err(|_| RemoteError::Fetch {
+            url: url.to_string(),
+            reason: "the module is not valid UTF-8".to_string(),
+        })?;
+        Ok(RemoteModule {
+            url: download.url,
+            content_type: download.content_type,
+            source,
+        })
+    }
+
+    async fn fetch(&self, url: &reqwest::Url) -> Result<Download, RemoteError> {
+        let fetch_error = |reason: String| RemoteError::Fetch {
+            url: url.to_string(),
+            reason,
+        };
+        let response =
+            self.client.get(url.clone()).send().await.map_err(
+                |e| match fetch::permission_denied(&e) {
+                    Some(denied) => RemoteError::Permission(denied.clone()),
+                    None => fetch_error(e.to_string()),
+                },
+            )?;
+        if !response.status().is_success() {
+            return Err(fetch_error(response.status().to_string()));
+        }
+        let final_url = response.url().clone();
+        let content_type = response
+            .headers()
+            .get(reqwest::header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_string);
+        let body = response
+            .bytes()
+            .await
+            .map_err(|e| fetch_error(e.to_string()))?;
+        Ok(Download {
+            url: final_url,
+            content_type,
+            body: body.to_vec(),
+        })
+    }
+
+    fn entry_path(&self, url: &reqwest::Url) -> PathBuf {
+        let name = sha256_hex(url.as_str().as_bytes());
+        self.options.cache_dir.join("urls").join(name)
+    }
+
+    fn blob_path(&self, hash: &str) -> PathBuf {
+        self.options.cache_dir.join("blobs").join(hash)
+    }
+
+    // A missing or unreadable entry is a cache miss, the module gets downloaded again.
+    fn read_cache(&self, url: &reqwest::Url) -> Option<Download> {
+        let entry = std::fs::read_to_string(self.entry_path(url)).ok()?;
+        let entry: CacheEntry = serde_json::from_str(&entry).ok()?;
+        let body = std::fs::read(self.blob_path(&entry.hash)).ok()?;
+        // Blobs are named after their contents, anything else means the cache got corrupted.
+        if sha256_hex(&body) != entry.hash {
+            return None;
+        }
+        let final_url = match entry.redirected_to {
+            Some(redirected_to) => reqwest::Url::parse(&redirected_to).ok()?,
+            None => url.clone(),
+        };
+        Some(Download {
+            url: final_url,
+            content_type: entry.content_type,
+            body,
+        })
+    }
+
+    fn write_cache(&self, url: &reqwest::Url, download: &Download) -> Result<(), RemoteError> {
+        let hash = sha256_hex(&download.body);
+        let blob_path = self.blob_path(&hash);
+        std::fs::create_dir_all(blob_path.parent().unwrap())?;
+        std::fs::write(&blob_path, &download.body)?;
+
+        let entry = CacheEntry {
+            url: url.to_string(),
+            redirected_to: (download.url != *url).then(|| download.url.to_string()),
+            hash,
+            content_type: download.content_type.clone(),
+        };
+        let entry_path = self.entry_path(url);
+        std::fs::create_dir_all(entry_path.parent().unwrap())?;
+        std::fs::write(entry_path, serde_json::to_string(&entry).unwrap())?;
+        Ok(())
+    }
+
+    // Records the hash of modules that are new to the lockfile, and fails for modules that changed since.
+    fn check_integrity(&self, url: &reqwest::Url, body: &[u8]) -> Result<(), RemoteError> {
+        let Some(path) = &self.options.lockfile else {
+            return Ok(());
+        };
+        let actual = format!("sha256-{}", sha256_hex(body));
+
+        let mut lockfile = self.lockfile.borrow_mut();
+        match lockfile.remote.get(url.as_str()) {
+            Some(expected) if *expected == actual => Ok(()),
+            Some(expected) => Err(RemoteError::Integrity {
+                url: url.to_string(),
+                expected: expected.clone(),
+                actual,
+                lockfile: path.display().to_string(),
+            }),
+            None => {
+                lockfile.remote.insert(url.to_string(), actual);
+                let contents = serde_json::to_string_pretty(&*lockfile).unwrap();
+                std::fs::write(path, contents + "\n")?;
+                Ok(())
+            }
+        }
+    }
+}
+
+fn sha256_hex(bytes: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(bytes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::permissions::Permissions;
+    use crate::test_util::{serve, serve_redirect};
+    use std::sync::{Arc, Mutex};
+
+    // Serves `body` at every path, counting the requests.
+    fn serve_module(body: Arc<Mutex<String>>, requests: Arc<Mutex<usize>>) -> u16 {
+        serve(move |_| {
+            *requests.lock().unwrap() += 1;
+            (
+                "200 OK",
+                "application/typescript",
+                body.lock().unwrap().clone(),
+            )
+        })
+    }
+
+    fn options(dir: &Path) -> RemoteOptions {
+        RemoteOptions {
+            cache_dir: dir.join("cache"),
+            lockfile: Some(dir.join("runjs.lock")),
+            reload: false,
+            cached_only: false,
+        }
+    }
+
+    fn allow_net() -> SharedPermissions {
+        net_permissions("--allow-net")
+    }
+
+    fn net_permissions(flag: &str) -> SharedPermissions {
+        let (permissions, _) = Permissions::from_args(vec![flag.to_string()]);
+        SharedPermissions::new(permissions.into())
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("runjs_{}_{name}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[tokio::test]
+    async fn test_modules_are_cached_and_locked() {
+        let dir = temp_dir("remote_cache");
+        let body = Arc::new(Mutex::new("export const answer: number = 42;".to_string()));
+        let requests = Arc::new(Mutex::new(0));
+        let port = serve_module(body.clone(), requests.clone());
+        let url = reqwest::Url::parse(&format!("http://127.0.0.1:{port}/mod.ts")).unwrap();
+
+        let module = RemoteModules::new(options(&dir), allow_net())
+            .unwrap()
+            .load(&url)
+            .await
+            .unwrap();
+        assert_eq!(module.source, "export const answer: number = 42;");
+        assert_eq!(
+            module.content_type.as_deref(),
+            Some("application/typescript")
+        );
+        assert_eq!(*requests.lock().unwrap(), 1);
+
+        let lockfile = std::fs::read_to_string(dir.join("runjs.lock")).unwrap();
+        let hash = sha256_hex(b"export const answer: number = 42;");
+        assert!(
+            lockfile.contains(&format!("\"{url}\": \"sha256-{hash}\"")),
+            "{lockfile}"
+        );
+        assert!(dir.join("cache").join("blobs").join(&hash).exists());
+
+        // The second run is served from the cache, even in --cached-only mode.
+        let cached_only = RemoteOptions {
+            cached_only: true,
+            ..options(&dir)
+        };
+        let cached = RemoteModules::new(cached_only, allow_net())
+            .unwrap()
+            .load(&url)
+            .await
+            .unwrap();
+        assert_eq!(cached, module);
+        assert_eq!(*requests.lock().unwrap(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_cached_only_fails_for_missing_modules() {
+        let dir = temp_dir("remote_cached_only");
+        let url = reqwest::Url::parse("http://127.0.0.1:1/missing.ts").unwrap();
+        let options = RemoteOptions {
+            cached_only: true,
+            ..options(&dir)
+        };
+
+        let error = RemoteModules::new(options, allow_net())
+            .unwrap()
+            .load(&url)
+            .await
+            .unwrap_err();
+        assert!(matches!(error, RemoteError::NotCached { .. }), "{error}");
+    }
+
+    #[tokio::test]
+    async fn test_reload_checks_the_lockfile() {
+        let dir = temp_dir("remote_reload");
+        let body = Arc::new(Mutex::new("export const version = 1;".to_string()));
+        let requests = Arc::new(Mutex::new(0));
+        let port = serve_module(body.clone(), requests.clone());
+        let url = reqwest::Url::parse(&format!("http://127.0.0.1:{port}/mod.js")).unwrap();
+
+        RemoteModules::new(options(&dir), allow_net())
+            .unwrap()
+            .load(&url)
+            .await
+            .unwrap();
+
+        // The module changed on the server, the cache hides it until it's reloaded.
+        *body.lock().unwrap() = "export const version = 2;".to_string();
+        let cached = RemoteModules::new(options(&dir), allow_net())
+            .unwrap()
+            .load(&url)
+            .await
+            .unwrap();
+        assert_eq!(cached.source, "export const version = 1;");
+
+        let reload = RemoteOptions {
+            reload: true,
+            ..options(&dir)
+        };
+        let error = RemoteModules::new(reload, allow_net())
+            .unwrap()
+            .load(&url)
+            .await
+            .unwrap_err();
+        assert!(matches!(error, RemoteError::Integrity { .. }), "{error}");
+        assert_eq!(*requests.lock().unwrap(), 2);
+
+        // Without a lockfile, the new version is accepted.
+        let unlocked = RemoteOptions {
+            reload: true,
+            lockfile: None,
+            ..options(&dir)
+        };
+        let reloaded = RemoteModules::new(unlocked, allow_net())
+            .unwrap()
+            .load(&url)
+            .await
+            .unwrap();
+        assert_eq!(reloaded.source, "export const version = 2;");
+    }
+
+    #[tokio::test]
+    async fn test_fetch_errors() {
+        let dir = temp_dir("remote_errors");
+        let port = serve(|_| ("404 Not Found", "text/plain", "not found".to_string()));
+        let url = reqwest::Url::parse(&format!("http://127.0.0.1:{port}/missing.ts")).unwrap();
+
+        let error = RemoteModules::new(options(&dir), allow_net())
+            .unwrap()
+            .load(&url)
+            .await
+            .unwrap_err();
+        assert!(matches!(error, RemoteError::Fetch { .. }), "{error}");
+        assert!(error.to_string().contains("404"), "{error}");
+    }
+
+    #[tokio::test]
+    async fn test_redirects() {
+        let dir = temp_dir("remote_redirects");
+        let body = Arc::new(Mutex::new("export const answer = 42;".to_string()));
+        let port = serve_module(body, Arc::new(Mutex::new(0)));
+        let redirect = serve_redirect(format!("http://127.0.0.1:{port}/answer.js"));
+        let url = reqwest::Url::parse(&format!("http://127.0.0.1:{redirect}/answer.js")).unwrap();
+
+        let permissions = net_permissions(&format!(
+            "--allow-net=127.0.0.1:{redirect},127.0.0.1:{port}"
+        ));
+        let module = RemoteModules::new(options(&dir), permissions.clone())
+            .unwrap()
+            .load(&url)
+            .await
+            .unwrap();
+        assert_eq!(
+            module.url.as_str(),
+            format!("http://127.0.0.1:{port}/answer.js")
+        );
+        assert_eq!(module.source, "export const answer = 42;");
+
+        // The cache remembers where the module came from.
+        let cached_only = RemoteOptions {
+            cached_only: true,
+            ..options(&dir)
+        };
+        let cached = RemoteModules::new(cached_only, permissions)
+            .unwrap()
+            .load(&url)
+            .await
+            .unwrap();
+        assert_eq!(cached, module);
+
+        // Only the host that redirects is allowed, not the one it redirects to.
+        let reload = RemoteOptions {
+            reload: true,
+            ..options(&dir)
+        };
+        let permissions = net_permissions(&format!("--allow-net=127.0.0.1:{redirect}"));
+        let error = RemoteModules::new(reload, permissions)
+            .unwrap()
+            .load(&url)
+            .await
+            .unwrap_err();
+        assert!(matches!(error, RemoteError::Permission(_)), "{error}");
+        assert!(
+            error
+                .to_string()
+                .starts_with(&format!("Requires net access to 127.0.0.1:{port}")),
+            "{error}"
+        );
+    }
+
+    #[test]
+    fn test_from_args() {
+        let args = ["--reload", "--lock=deps.lock", "--cached-only", "main.ts"];
+        let (options, rest) =
+            RemoteOptions::from_args(args.iter().map(|a| a.to_string()).collect());
+
+        assert_eq!(rest, vec!["main.ts"]);
+        assert!(options.reload && options.cached_only);
+        assert_eq!(options.lockfile, Some(PathBuf::from("deps.lock")));
+
+        // Flags after the script are the script's own.
+        let args = ["--reload", "main.ts", "--cached-only", "--no-lock"];
+        let (options, rest) =
+            RemoteOptions::from_args(args.iter().map(|a| a.to_string()).collect());
+
+        assert_eq!(rest, vec!["main.ts", "--cached-only", "--no-lock"]);
+        assert!(options.reload && !options.cached_only);
+        assert_eq!(options.lockfile, Some(PathBuf::from(DEFAULT_LOCKFILE)));
+
+        let (options, _) = RemoteOptions::from_args(vec!["--no-lock".to_string()]);
+        assert_eq!(options.lockfile, None);
+    }
+}
@@ -0,0 +1,400 @@
+// The REPL that `runjs` starts when it's not given a file.
+//
+// Every input is transpiled from TypeScript on its own, and then run as the body of an async function, which is what
+// allows top-level `await`. Declarations would stay local to that function, so each declared name is also assigned to
+// `globalThis` for the inputs after it to use, and the value of the last expression is returned to be printed.
+// Static imports aren't allowed in a function either, so they're rewritten to dynamic ones. The input is rewritten
+// from the AST of the transpiled code.
+
+use deno_ast::EmitOptions;
+use deno_ast::MediaType;
+use deno_ast::ParseDiagnostic;
+use deno_ast::ParseParams;
+use deno_ast::ParsedSource;
+use deno_ast::SourceMapOption;
+use deno_ast::SourceRangedForSpanned;
+use deno_ast::TranspileOptions;
+use deno_ast::swc::ast::{
+    Decl, ImportDecl, ImportSpecifier, ModuleDecl, ModuleItem, ObjectPatProp, Pat, Stmt,
+};
+use deno_ast::swc::parser::error::SyntaxError;
+use deno_core::JsRuntime;
+use deno_core::ModuleSpecifier;
+use deno_core::PollEventLoopOptions;
+use deno_core::error::AnyError;
+use rustyline::error::ReadlineError;
+use rustyline::history::DefaultHistory;
+use rustyline::validate::{ValidationContext, ValidationResult, Validator};
+use rustyline::{Completer, Editor, Helper, Highlighter, Hinter};
+use std::path::Path;
+
+/// Starts the REPL on the runtime, keeping the input history in `history_path`. It runs until the input ends, with
+/// Ctrl+D.
+pub async fn run_repl(mut js_runtime: JsRuntime, history_path: &Path) -> Result<(), AnyError> {
+    // Dynamic imports are resolved against the name of the script, so it's named like a module in the current
+    // directory. Scripts need a static name, there's only one for the whole session.
+    let specifier = ModuleSpecifier::from_file_path(std::env::current_dir()?.join("$repl.ts"))
+        .map_err(|()| AnyError::msg("The current directory isn't an absolute path"))?;
+    let script_name: &'static str = String::leak(specifier.to_string());
+
+    let mut editor = Editor::<ReplHelper, DefaultHistory>::new()?;
+    editor.set_helper(Some(ReplHelper { specifier }));
+    // There's no history yet the first time.
+    let _ = editor.load_history(history_path);
+
+    println!("runjs {}", env!("CARGO_PKG_VERSION"));
+    println!("exit using ctrl+d");
+    loop {
+        let input = match editor.readline("> ") {
+            Ok(input) => input,
+            // Ctrl+C drops the input, like in a shell.
+            Err(ReadlineError::Interrupted) => continue,
+            Err(ReadlineError::Eof) => break,
+            Err(error) => return Err(error.into()),
+        };
+        if input.trim().is_empty() {
+            continue;
+        }
+        editor.add_history_entry(input.as_str())?;
+
+        if let Err(error) = evaluate(&mut js_runtime, script_name, &input).await {
+            eprintln!("error: {}", error);
+        }
+    }
+
+    if let Some(dir) = history_path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    editor.save_history(history_path)?;
+    Ok(())
+}
+
+/// Runs one input in the REPL as the script `script_name`, a module specifier, and prints its value.
+pub async fn evaluate(
+    js_runtime: &mut JsRuntime,
+    script_name: &'static str,
+    input: &str,
+) -> Result<(), AnyError> {
+    let specifier = ModuleSpecifier::parse(script_name)?;
+    let code = transpile(&specifier, input)?;
+    let script = format!(
+        "{}.then((value) => console.dir(value))",
+        wrap_input(&specifier, &code)?
+    );
+    let promise = js_runtime.execute_script(script_name, script)?;
+    let result = js_runtime.resolve(promise);
+    js_runtime
+        .with_event_loop_promise(result, PollEventLoopOptions::default())
+        .await?;
+    Ok(())
+}
+
+fn transpile(specifier: &ModuleSpecifier, input: &str) -> Result<String, AnyError> {
+    let parsed = parse(specifier, input, MediaType::TypeScript)?;
+    let code = parsed
+        .transpile(
+            // Only `type` imports are removed, names the input imports without using are for the inputs after it.
+            &TranspileOptions {
+                verbatim_module_syntax: true,
+                ..Default::default()
+            },
+            &Default::default(),
+            &EmitOptions {
+                source_map: SourceMapOption::None,
+                ..Default::default()
+            },
+        )?
+        .into_source()
+        .text;
+    Ok(code)
+}
+
+// Asks for more lines while the input ends in the middle of a statement, a template literal or a comment.
+#[derive(Completer, Helper, Highlighter, Hinter)]
+struct ReplHelper {
+    specifier: ModuleSpecifier,
+}
+
+impl Validator for ReplHelper {
+    fn validate(&self, ctx: &mut ValidationContext) -> rustyline::Result<ValidationResult> {
+        if is_incomplete(&self.specifier, ctx.input()) {
+            Ok(ValidationResult::Incomplete)
+        } else {
+            Ok(ValidationResult::Valid(None))
+        }
+    }
+}
+
+/// Whether more lines are needed to finish the input, which is when it fails to parse because it ended too early.
+/// Input with any other syntax error is complete, for it to fail when it's run.
+pub fn is_incomplete(specifier: &ModuleSpecifier, input: &str) -> bool {
+    let parsed = parse(specifier, input, MediaType::TypeScript);
+    let diagnostics = match &parsed {
+        Ok(parsed) => parsed.diagnostics().iter().collect(),
+        Err(diagnostic) => vec![&**diagnostic],
+    };
+    diagnostics.iter().any(|diagnostic| match &diagnostic.kind {
+        SyntaxError::Eof | SyntaxError::UnterminatedBlockComment | SyntaxError::UnterminatedTpl => {
+            true
+        }
+        SyntaxError::Expected(_, found) => found == "<eof>",
+        _ => false,
+    })
+}
+
+/// Wraps transpiled input in an async function that declares its names globally too and returns the value of its last
+/// expression. The result is an expression for the promise of that value.
+pub fn wrap_input(specifier: &ModuleSpecifier, code: &str) -> Result<String, AnyError> {
+    let parsed = parse(specifier, code, MediaType::JavaScript)?;
+    let program = parsed.program_ref();
+    let items = &program.unwrap_module().body;
+
+    // Function declarations are hoisted, so they can be made global before anything else runs.
+    let mut hoisted = String::new();
+    let mut body = String::new();
+    for (index, item) in items.iter().enumerate() {
+        let mut declared = Vec::new();
+        match item {
+            ModuleItem::ModuleDecl(ModuleDecl::Import(import)) => {
+                body.push_str(&dynamic_import(&parsed, import, &mut declared));
+            }
+            ModuleItem::ModuleDecl(_) => {
+                return Err(AnyError::msg(
+                    "Exports can't be used in the REPL, there's no module to export from",
+                ));
+            }
+            ModuleItem::Stmt(Stmt::Expr(statement)) if index == items.len() - 1 => {
+                let expression = statement.expr.text_fast(&parsed);
+                body.push_str(&format!("return ({expression});\n"));
+                continue;
+            }
+            ModuleItem::Stmt(statement) => {
+                match statement {
+                    Stmt::Decl(Decl::Var(var)) => {
+                        for declarator in &var.decls {
+                            pattern_names(&declarator.name, &mut declared);
+                        }
+                    }
+                    Stmt::Decl(Decl::Fn(function)) => {
+                        let name = &function.ident.sym;
+                        hoisted.push_str(&format!("globalThis.{name} = {name};\n"));
+                    }
+                    Stmt::Decl(Decl::Class(class)) => declared.push(class.ident.sym.to_string()),
+                    _ => {}
+                }
+                body.push_str(item.text_fast(&parsed));
+                body.push('\n');
+            }
+        }
+        for name in declared {
+            body.push_str(&format!("globalThis.{name} = {name};\n"));
+        }
+    }
+    Ok(format!("(async () => {{\n{hoisted}{body}}})()"))
+}
+
+fn parse(
+    specifier: &ModuleSpecifier,
+    code: &str,
+    media_type: MediaType,
+) -> Result<ParsedSource, Box<ParseDiagnostic>> {
+    // Parsed as a module, scripts don't allow top-level `await` or imports.
+    deno_ast::parse_module(ParseParams {
+        specifier: specifier.clone(),
+        text: code.into(),
+        media_type,
+        capture_tokens: false,
+        scope_analysis: false,
+        maybe_syntax: None,
+    })
+    .map_err(Box::new)
+}
+
+// Static imports can't be used in a function, they're turned into a dynamic import destructuring the same names.
+fn dynamic_import(
+    parsed: &ParsedSource,
+    import: &ImportDecl,
+    declared: &mut Vec<String>,
+) -> String {
+    let mut call = format!("await import({}", import.src.text_fast(parsed));
+    if let Some(attributes) = &import.with {
+        call.push_str(&format!(", {{ with: {} }}", attributes.text_fast(parsed)));
+    }
+    call.push(')');
+
+    let mut namespace = None;
+    let mut properties = Vec::new();
+    for specifier in &import.specifiers {
+        let local = match specifier {
+            ImportSpecifier::Namespace(specifier) => {
+                namespace = Some(&specifier.local.sym);
+                &specifier.local
+            }
+            ImportSpecifier::Default(specifier) => {
+                properties.push(format!("default: {}", specifier.local.sym));
+                &specifier.local
+            }
+            ImportSpecifier::Named(specifier) => {
+                properties.push(match &specifier.imported {
+                    Some(imported) => {
+                        format!("{}: {}", imported.text_fast(parsed), specifier.local.sym)
+                    }
+                    None => specifier.local.sym.to_string(),
+                });
+                &specifier.local
+            }
+        };
+        declared.push(local.sym.to_string());
+    }
+
+    let properties = properties.join(", ");
+    match namespace {
+        Some(namespace) if properties.is_empty() => format!("const {namespace} = {call};\n"),
+        Some(namespace) => {
+            format!("const {namespace} = {call};\nconst {{ {properties} }} = {namespace};\n")
+        }
+        None if properties.is_empty() => format!("{call};\n"),
+        None => format!("const {{ {properties} }} = {call};\n"),
+    }
+}
+
+// The names bound by a binding pattern, which is a name, or an object or array destructuring other patterns.
+fn pattern_names(pattern: &Pat, names: &mut Vec<String>) {
+    match pattern {
+        Pat::Ident(binding) => names.push(binding.id.sym.to_string()),
+        Pat::Array(array) => {
+            for element in array.elems.iter().flatten() {
+                pattern_names(element, names);
+            }
+        }
+        Pat::Object(object) => {
+            for property in &object.props {
+                match property {
+                    ObjectPatProp::KeyValue(property) => pattern_names(&property.value, names),
+                    ObjectPatProp::Assign(property) => names.push(property.key.id.sym.to_string()),
+                    ObjectPatProp::Rest(rest) => pattern_names(&rest.arg, names),
+                }
+            }
+        }
+        Pat::Rest(rest) => pattern_names(&rest.arg, names),
+        // `name = default` binds the name.
+        Pat::Assign(assign) => pattern_names(&assign.left, names),
+        Pat::Expr(_) | Pat::Invalid(_) => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::create_runtime;
+    use crate::permissions::Permissions;
+    use crate::remote::RemoteOptions;
+
+    fn specifier() -> ModuleSpecifier {
+        ModuleSpecifier::parse("file:///$repl.ts").unwrap()
+    }
+
+    fn wrap(code: &str) -> String {
+        wrap_input(&specifier(), code).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_evaluate_keeps_declarations() {
+        let remote_options = RemoteOptions {
+            cache_dir: std::env::temp_dir().join(format!("runjs_{}_cache", std::process::id())),
+            ..Default::default()
+        };
+        let (mut js_runtime, _) =
+            create_runtime(Permissions::default(), remote_options, None).unwrap();
+
+        let inputs = [
+            "const base: number = await Promise.resolve(40);",
+            "function add(a: number, b: number): number {\n    return a + b;\n}",
+            "let { total } = { total: add(base, 2) };",
+            "if (total !== 42) throw new Error(`expected 42, got ${total}`);",
+        ];
+        for input in inputs {
+            evaluate(&mut js_runtime, "file:///$repl.ts", input)
+                .await
+                .unwrap();
+        }
+
+        let error = evaluate(
+            &mut js_runtime,
+            "file:///$repl.ts",
+            "await Promise.reject(new Error('boom'));",
+        )
+        .await
+        .unwrap_err();
+        assert!(error.to_string().contains("boom"));
+    }
+
+    #[test]
+    fn test_is_incomplete() {
+        let is_incomplete = |input| is_incomplete(&specifier(), input);
+        assert!(!is_incomplete("1 + 2"));
+        assert!(!is_incomplete("const f = () => { return [1, 2]; };"));
+        assert!(is_incomplete("function f() {"));
+        assert!(is_incomplete("const list = [\n  1,"));
+        assert!(is_incomplete("const text = `line\n"));
+        assert!(is_incomplete("const text = `${value"));
+        assert!(is_incomplete("/* comment"));
+        assert!(!is_incomplete("const text = `${{ a: 1 }.a} }`;"));
+        assert!(!is_incomplete("const brace = '{';"));
+        assert!(!is_incomplete("const regex = /[({]/g;"));
+        assert!(!is_incomplete("const half = total / 2; // {"));
+        // Too many closing brackets is a syntax error, not more input.
+        assert!(!is_incomplete("f())"));
+        assert!(!is_incomplete("const text = 'line"));
+    }
+
+    #[test]
+    fn test_wrap_input_returns_the_last_expression() {
+        assert_eq!(wrap("1 + 2;\n"), "(async () => {\nreturn (1 + 2);\n})()");
+        assert_eq!(
+            wrap("await fetch(url);\nresult.status;\n"),
+            "(async () => {\nawait fetch(url);\nreturn (result.status);\n})()"
+        );
+        assert_eq!(
+            wrap("if (ok) {\n    done();\n} else {\n    retry();\n}\n"),
+            "(async () => {\nif (ok) {\n    done();\n} else {\n    retry();\n}\n})()"
+        );
+        assert_eq!(
+            wrap("({\n    a: 1\n});\n"),
+            "(async () => {\nreturn (({\n    a: 1\n}));\n})()"
+        );
+    }
+
+    #[test]
+    fn test_wrap_input_declares_names_globally() {
+        assert_eq!(
+            wrap("const a = 1, { b, c: [d, ...e], f = g ? h : i } = await load();\n"),
+            "(async () => {\nconst a = 1, { b, c: [d, ...e], f = g ? h : i } = await load();\n\
+             globalThis.a = a;\nglobalThis.b = b;\nglobalThis.d = d;\nglobalThis.e = e;\nglobalThis.f = f;\n})()"
+        );
+        assert_eq!(
+            wrap("async function load() {\n    return 1;\n}\nclass Point {\n}\n"),
+            "(async () => {\nglobalThis.load = load;\nasync function load() {\n    return 1;\n}\nclass Point {\n}\n\
+             globalThis.Point = Point;\n})()"
+        );
+        assert_eq!(
+            wrap("let [x, , y = 2] = point;\n"),
+            "(async () => {\nlet [x, , y = 2] = point;\nglobalThis.x = x;\nglobalThis.y = y;\n})()"
+        );
+    }
+
+    #[test]
+    fn test_wrap_input_rewrites_imports() {
+        assert_eq!(
+            wrap(
+                "import config, * as all from \"./config.ts\";\n\
+                 import { a, \"b-c\" as b } from './data.json' with { type: \"json\" };\nimport \"./setup.js\";\n"
+            ),
+            "(async () => {\nconst all = await import(\"./config.ts\");\nconst { default: config } = all;\n\
+             globalThis.config = config;\nglobalThis.all = all;\n\
+             const { a, \"b-c\": b } = await import('./data.json', { with: { type: \"json\" } });\n\
+             globalThis.a = a;\nglobalThis.b = b;\nawait import(\"./setup.js\");\n})()"
+        );
+        assert!(wrap_input(&specifier(), "export const a = 1;\n").is_err());
+    }
+}
@@ -0,0 +1,458 @@
+// `runjs test`, which finds the test files under the given paths and runs the tests they register with `runjs.test`.
+//
+// Every file runs in a runtime of its own. Once it has loaded, the tests it registered run one after the other, and
+// each result comes back through `op_test_event` to be printed as it happens. Failures are printed in full at the end,
+// before the summary.
+
+use crate::create_runtime;
+use crate::import_map::ImportMap;
+use crate::permissions::Permissions;
+use crate::remote::RemoteOptions;
+use deno_core::OpState;
+use deno_core::PollEventLoopOptions;
+use deno_core::error::AnyError;
+use deno_core::op2;
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+const TEST_FILE_SUFFIXES: [&str; 8] = [
+    "_test.ts",
+    ".test.ts",
+    "_test.tsx",
+    ".test.tsx",
+    "_test.js",
+    ".test.js",
+    "_test.jsx",
+    ".test.jsx",
+];
+
+/// How long a test may take by default, in milliseconds.
+const DEFAULT_TIMEOUT: u64 = 5000;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TestOptions {
+    /// Files and directories to look for tests in.
+    pub paths: Vec<PathBuf>,
+    /// Only runs the tests whose name includes it, or matches it if it's a regular expression wrapped in slashes.
+    pub filter: Option<String>,
+    /// How long each test may take, in milliseconds, unless the test sets its own timeout. 0 is no limit.
+    pub timeout: u64,
+}
+
+impl TestOptions {
+    /// Reads `--filter=<text>`, `--timeout=<ms>` and the paths from the arguments after `test`. Without paths, tests
+    /// are looked for in the current directory. The other flags of runjs are only read before `test`, so they're an
+    /// error here rather than a path.
+    pub fn from_args(args: Vec<String>) -> Result<Self, AnyError> {
+        let mut options = Self {
+            paths: Vec::new(),
+            filter: None,
+            timeout: DEFAULT_TIMEOUT,
+        };
+        for arg in args {
+            if let Some(filter) = arg.strip_prefix("--filter=") {
+                options.filter = Some(filter.to_string());
+            } else if let Some(timeout) = arg.strip_prefix("--timeout=") {
+                options.timeout = timeout.parse().map_err(|_| {
+                    AnyError::msg(format!("Invalid timeout {timeout}, expected milliseconds"))
+                })?;
+            } else if arg.starts_with('-') {
+                let flag = arg.split_once('=').map_or(arg.as_str(), |(flag, _)| flag);
+                return Err(AnyError::msg(format!(
+                    "{flag} isn't an option of `test`, runjs options like it must come before `test`"
+                )));
+            } else {
+                options.paths.push(PathBuf::from(arg));
+            }
+        }
+        if options.paths.is_empty() {
+            options.paths.push(PathBuf::from("."));
+        }
+        Ok(options)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Outcome {
+    Ok,
+    Failed,
+    Ignored,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TestResult {
+    name: String,
+    outcome: Outcome,
+    // In milliseconds.
+    duration: f64,
+    error: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum TestEvent {
+    Plan { total: usize, filtered: usize },
+    Result(TestResult),
+}
+
+// The results of the test file being run, kept in the op state.
+#[derive(Debug, Default)]
+struct FileReport {
+    file: String,
+    filtered: usize,
+    results: Vec<TestResult>,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Summary {
+    pub passed: usize,
+    pub failed: usize,
+    pub ignored: usize,
+    pub filtered: usize,
+}
+
+#[op2]
+pub fn op_test_event(state: &mut OpState, #[serde] event: TestEvent) {
+    // Tests only run under `runjs test`.
+    let Some(report) = state.try_borrow_mut::<FileReport>() else {
+        return;
+    };
+    match event {
+        TestEvent::Plan { total, filtered } => {
+            report.filtered = filtered;
+            println!("running {} from {}", plural(total, "test"), report.file);
+        }
+        TestEvent::Result(result) => {
+            println!("{}", format_result(&result));
+            report.results.push(result);
+        }
+    }
+}
+
+/// Runs the tests of every test file under the paths, printing the results, and returns how many passed and failed.
+pub async fn run_tests(
+    options: &TestOptions,
+    permissions: Permissions,
+    remote_options: RemoteOptions,
+    import_map: Option<ImportMap>,
+) -> Result<Summary, AnyError> {
+    let files = find_test_files(&options.paths)?;
+    if files.is_empty() {
+        return Err(AnyError::msg("No test files found"));
+    }
+
+    let started = Instant::now();
+    let mut summary = Summary::default();
+    // The name of each failed test, or file, with its error.
+    let mut failures = Vec::new();
+    for file in files {
+        let name = file.display().to_string();
+        let result = run_file(
+            &file,
+            &name,
+            options,
+            permissions.clone(),
+            remote_options.clone(),
+            import_map.clone(),
+        )
+        .await;
+        match result {
+            Ok(report) => {
+                summary.filtered += report.filtered;
+                for result in report.results {
+                    match result.outcome {
+                        Outcome::Ok => summary.passed += 1,
+                        Outcome::Ignored => summary.ignored += 1,
+                        Outcome::Failed => {
+                            summary.failed += 1;
+                            let error = result.error.unwrap_or_default();
+                            failures.push((format!("{} ({name})", result.name), error));
+                        }
+                    }
+                }
+            }
+            // The file failed to load, or threw outside of a test.
+            Err(error) => {
+                println!("{name} ... FAILED");
+                summary.failed += 1;
+                failures.push((name, error.to_string()));
+            }
+        }
+    }
+
+    if !failures.is_empty() {
+        println!("\nfailures:");
+        for (name, error) in &failures {
+            println!("\n---- {name} ----\n{error}");
+        }
+    }
+    println!("\n{}", format_summary(&summary, started.elapsed()));
+    Ok(summary)
+}
+
+async fn run_file(
+    file: &Path,
+    name: &str,
+    options: &TestOptions,
+    permissions: Permissions,
+    remote_options: RemoteOptions,
+    import_map: Option<ImportMap>,
+) -> Result<FileReport, AnyError> {
+    let specifier = deno_core::resolve_path(&file.to_string_lossy(), &std::env::current_dir()?)?;
+    let (mut js_runtime, loader) = create_runtime(permissions, remote_options, import_map)?;
+    js_runtime.op_state().borrow_mut().put(FileReport {
+        file: name.to_string(),
+        ..Default::default()
+    });
+
+    let result = async {
+        // Only wait for the module itself to evaluate, timers it started keep running alongside the tests.
+        let mod_id = js_runtime.load_main_es_module(&specifier).await?;
+        let evaluation = js_runtime.mod_evaluate(mod_id);
+        js_runtime
+            .with_event_loop_promise(evaluation, PollEventLoopOptions::default())
+            .await?;
+
+        let script = format!(
+            "globalThis[Symbol.for(\"runjs.runTests\")]({})",
+            serde_json::json!({ "filter": options.filter, "timeout": options.timeout })
+        );
+        let promise = js_runtime.execute_script("[runjs:test]", script)?;
+        let result = js_runtime.resolve(promise);
+        js_runtime
+            .with_event_loop_promise(result, PollEventLoopOptions::default())
+            .await?;
+        Ok::<_, AnyError>(())
+    }
+    .await;
+
+    // Errors show the original code where they were thrown, like when running a script.
+    let with_code_frame = |error: String| match loader.code_frame_for(&error) {
+        Some(frame) => format!("{error}\n\n{frame}"),
+        None => error,
+    };
+    if let Err(error) = result {
+        return Err(AnyError::msg(with_code_frame(error.to_string())));
+    }
+    let mut report = js_runtime.op_state().borrow_mut().take::<FileReport>();
+    for result in &mut report.results {
+        result.error = result.error.take().map(with_code_frame);
+    }
+    Ok(report)
+}
+
+/// Returns the test files among the paths and in the directories among them, in order. Files that are given directly
+/// are run whatever their name.
+fn find_test_files(paths: &[PathBuf]) -> std::io::Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    for path in paths {
+        if path.is_dir() {
+            find_in_dir(path, &mut files)?;
+        } else if path.exists() {
+            files.push(path.clone());
+        } else {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                format!("{} doesn't exist", path.display()),
+            ));
+        }
+    }
+    files.sort();
+    files.dedup();
+    Ok(files)
+}
+
+// Hidden directories and dependencies aren't searched.
+fn find_in_dir(dir: &Path, files: &mut Vec<PathBuf>) -> std::io::Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+        if path.is_dir() {
+            if !file_name.starts_with('.') && file_name != "node_modules" {
+                find_in_dir(&path, files)?;
+            }
+        } else if TEST_FILE_SUFFIXES
+            .iter()
+            .any(|suffix| file_name.ends_with(suffix))
+        {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+fn format_result(result: &TestResult) -> String {
+    match result.outcome {
+        Outcome::Ok => format!("{} ... ok ({}ms)", result.name, result.duration),
+        Outcome::Failed => format!("{} ... FAILED ({}ms)", result.name, result.duration),
+        Outcome::Ignored => format!("{} ... ignored", result.name),
+    }
+}
+
+fn format_summary(summary: &Summary, elapsed: Duration) -> String {
+    let status = if summary.failed == 0 { "ok" } else { "FAILED" };
+    format!(
+        "test result: {status}. {} passed; {} failed; {} ignored; {} filtered out; finished in {:.2}s",
+        summary.passed,
+        summary.failed,
+        summary.ignored,
+        summary.filtered,
+        elapsed.as_secs_f64()
+    )
+}
+
+fn plural(count: usize, noun: &str) -> String {
+    if count == 1 {
+        format!("{count} {noun}")
+    } else {
+        format!("{count} {noun}s")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("runjs_{}_{name}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_options_from_args() {
+        let args = ["--filter=add", "--timeout=100", "tests", "math_test.ts"].map(String::from);
+        assert_eq!(
+            TestOptions::from_args(args.to_vec()).unwrap(),
+            TestOptions {
+                paths: vec![PathBuf::from("tests"), PathBuf::from("math_test.ts")],
+                filter: Some("add".to_string()),
+                timeout: 100,
+            }
+        );
+
+        let options = TestOptions::from_args(Vec::new()).unwrap();
+        assert_eq!(options.paths, vec![PathBuf::from(".")]);
+        assert_eq!(options.timeout, DEFAULT_TIMEOUT);
+        assert!(TestOptions::from_args(vec!["--timeout=soon".to_string()]).is_err());
+
+        // Permission and remote module flags only count before `test`.
+        let error = TestOptions::from_args(vec!["tests".to_string(), "--allow-read=.".to_string()])
+            .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "--allow-read isn't an option of `test`, runjs options like it must come before `test`"
+        );
+        assert!(TestOptions::from_args(vec!["--reload".to_string()]).is_err());
+    }
+
+    #[test]
+    fn test_find_test_files() {
+        let dir = temp_dir("find_test_files");
+        for file in [
+            "math_test.ts",
+            "math.test.ts",
+            "math.ts",
+            "nested/util_test.js",
+            "node_modules/dep/dep_test.ts",
+            ".hidden/hidden_test.ts",
+        ] {
+            let path = dir.join(file);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, "").unwrap();
+        }
+
+        let files = find_test_files(&[dir.clone(), dir.join("math.ts")]).unwrap();
+        assert_eq!(
+            files,
+            [
+                "math.test.ts",
+                "math.ts",
+                "math_test.ts",
+                "nested/util_test.js"
+            ]
+            .map(|file| dir.join(file))
+        );
+        assert!(find_test_files(&[dir.join("missing")]).is_err());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_format_summary() {
+        let summary = Summary {
+            passed: 3,
+            failed: 1,
+            ignored: 0,
+            filtered: 2,
+        };
+        assert_eq!(
+            format_summary(&summary, Duration::from_millis(1250)),
+            "test result: FAILED. 3 passed; 1 failed; 0 ignored; 2 filtered out; finished in 1.25s"
+        );
+        assert_eq!(plural(1, "test"), "1 test");
+        assert_eq!(plural(2, "test"), "2 tests");
+    }
+
+    #[tokio::test]
+    async fn test_run_tests() {
+        let dir = temp_dir("run_tests");
+        std::fs::write(
+            dir.join("math_test.ts"),
+            r#"
+            const { test, assert, assertEquals, assertThrows, assertRejects } = runjs;
+
+            test("adds", () => assertEquals(1 + 2, 3));
+            test("compares structurally", () => {
+                assertEquals({ list: [1, { a: new Map([["k", 1]]) }] }, { list: [1, { a: new Map([["k", 1]]) }] });
+            });
+            test("waits for async tests", async () => {
+                const value = await new Promise((resolve) => setTimeout(() => resolve(42), 10));
+                assertEquals(value, 42);
+            });
+            test("checks errors", async () => {
+                assertThrows(() => JSON.parse("{"), SyntaxError);
+                await assertRejects(() => Promise.reject(new Error("boom")), Error, "boom");
+            });
+            test("fails", () => assert(false, "expected failure"));
+            test("times out", () => new Promise(() => setTimeout(() => {}, 1000)), { timeout: 20 });
+            test("is ignored", () => {}, { ignore: true });
+            test("filtered out", () => {});
+            "#,
+        )
+        .unwrap();
+        // The tests run while the timers the module started are still pending.
+        std::fs::write(
+            dir.join("interval_test.js"),
+            r#"
+            const interval = setInterval(() => {}, 10);
+            runjs.test("stops the interval", () => clearInterval(interval));
+            "#,
+        )
+        .unwrap();
+
+        let options = TestOptions {
+            paths: vec![dir.clone()],
+            filter: Some("/^(?!filtered)/".to_string()),
+            timeout: DEFAULT_TIMEOUT,
+        };
+        let remote_options = RemoteOptions {
+            cache_dir: dir.join("cache"),
+            ..Default::default()
+        };
+        let summary = run_tests(&options, Permissions::default(), remote_options, None).await;
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(
+            summary.unwrap(),
+            Summary {
+                passed: 5,
+                failed: 2,
+                ignored: 1,
+                filtered: 1,
+            }
+        );
+    }
+}
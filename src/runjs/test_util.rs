@@ -0,0 +1,135 @@
+// Helpers for the tests that run scripts through the whole runtime, and a stand-in HTTP server for them to talk to.
+
+use crate::permissions::Permissions;
+use crate::remote::RemoteOptions;
+use crate::run_js;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpListener;
+
+/// Defines `assertEquals(actual, expected)`, comparing the two through `JSON.stringify`.
+pub const ASSERT: &str = r#"
+    function assertEquals(actual, expected) {
+        if (JSON.stringify(actual) !== JSON.stringify(expected)) {
+            throw new Error(`expected ${JSON.stringify(expected)}, got ${JSON.stringify(actual)}`);
+        }
+    }
+"#;
+
+/// Runs the script as a module, saved under `file_name` in a temporary directory. Scripts fail the run by throwing,
+/// usually from a failed assertion.
+pub async fn run_script(
+    file_name: &str,
+    source: &str,
+    permissions: Permissions,
+) -> Result<(), deno_core::error::AnyError> {
+    let path = std::env::temp_dir().join(format!("runjs_{}_{file_name}", std::process::id()));
+    std::fs::write(&path, source).unwrap();
+    // Remote modules are cached away from the user's cache.
+    let remote_options = RemoteOptions {
+        cache_dir: std::env::temp_dir().join(format!("runjs_{}_cache", std::process::id())),
+        ..Default::default()
+    };
+    let result = run_js(path.to_str().unwrap(), permissions, remote_options, None).await;
+    std::fs::remove_file(&path).unwrap();
+    result
+}
+
+/// A request received by the stand-in server started with [`serve`].
+pub struct StandInRequest {
+    pub method: String,
+    pub path: String,
+    // Names are lowercased.
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl StandInRequest {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// Starts a stand-in HTTP server on a free local port and returns the port. Every request is answered with the
+/// status, content type and body that `handler` returns for it.
+pub fn serve<F>(handler: F) -> u16
+where
+    F: Fn(&StandInRequest) -> (&'static str, &'static str, String) + Send + 'static,
+{
+    serve_with_headers(move |request| {
+        let (status, content_type, body) = handler(request);
+        (
+            status,
+            vec![("content-type", content_type.to_string())],
+            body,
+        )
+    })
+}
+
+/// Starts a stand-in HTTP server that redirects every request to `location`, and returns its port.
+pub fn serve_redirect(location: String) -> u16 {
+    serve_with_headers(move |_| {
+        (
+            "302 Found",
+            vec![("location", location.clone())],
+            String::new(),
+        )
+    })
+}
+
+fn serve_with_headers<F>(handler: F) -> u16
+where
+    F: Fn(&StandInRequest) -> (&'static str, Vec<(&'static str, String)>, String) + Send + 'static,
+{
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = stream.unwrap();
+            let mut reader = BufReader::new(&stream);
+
+            let mut request_line = String::new();
+            reader.read_line(&mut request_line).unwrap();
+            let mut parts = request_line.split_whitespace();
+            let method = parts.next().unwrap_or_default().to_string();
+            let path = parts.next().unwrap_or_default().to_string();
+
+            let mut headers = Vec::new();
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                let Some((name, value)) = line.trim_end().split_once(':') else {
+                    break;
+                };
+                headers.push((name.to_ascii_lowercase(), value.trim().to_string()));
+            }
+            let mut request = StandInRequest {
+                method,
+                path,
+                headers,
+                body: Vec::new(),
+            };
+            let content_length = request
+                .header("content-length")
+                .map_or(0, |length| length.parse().unwrap());
+            request.body = vec![0; content_length];
+            reader.read_exact(&mut request.body).unwrap();
+
+            let (status, headers, body) = handler(&request);
+            let headers: String = headers
+                .iter()
+                .map(|(name, value)| format!("{name}: {value}\r\n"))
+                .collect();
+            let response = format!(
+                "HTTP/1.1 {status}\r\n{headers}x-server: stand-in\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{body}",
+                body.len()
+            );
+            stream.write_all(response.as_bytes()).unwrap();
+        }
+    });
+
+    port
+}
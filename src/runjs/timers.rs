@@ -0,0 +1,150 @@
+// The ops behind `setTimeout` and `setInterval`.
+//
+// Every timer is a resource holding a cancel handle. Sleeping is an async op, so a pending timer keeps the event
+// loop alive like any other pending op. Clearing the timer closes its resource, which cancels the sleep and lets the
+// event loop finish without waiting for it.
+
+use deno_core::CancelFuture;
+use deno_core::CancelHandle;
+use deno_core::OpState;
+use deno_core::RcRef;
+use deno_core::Resource;
+use deno_core::ResourceId;
+use deno_core::op2;
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::time::Duration;
+
+struct TimerResource {
+    cancel: CancelHandle,
+}
+
+impl Resource for TimerResource {
+    fn name(&self) -> Cow<'_, str> {
+        "timer".into()
+    }
+
+    fn close(self: Rc<Self>) {
+        self.cancel.cancel();
+    }
+}
+
+#[op2(fast)]
+#[smi]
+pub fn op_timer_start(state: &mut OpState) -> ResourceId {
+    state.resource_table.add(TimerResource {
+        cancel: CancelHandle::new(),
+    })
+}
+
+/// Sleeps for `delay` milliseconds, returning false if the timer was cleared in the meantime.
+#[op2(async)]
+pub async fn op_timer_sleep(
+    state: Rc<RefCell<OpState>>,
+    #[smi] rid: ResourceId,
+    delay: f64,
+) -> bool {
+    let Ok(timer) = state.borrow().resource_table.get::<TimerResource>(rid) else {
+        return false;
+    };
+    let cancel = RcRef::map(&timer, |timer| &timer.cancel);
+    tokio::time::sleep(Duration::from_millis(delay as u64))
+        .or_cancel(cancel)
+        .await
+        .is_ok()
+}
+
+#[op2(fast)]
+pub fn op_timer_clear(state: &mut OpState, #[smi] rid: ResourceId) {
+    // `runtime.js` only clears timers that are still running, so the resource is there unless a script reached for
+    // the op directly.
+    if let Ok(timer) = state.resource_table.take_any(rid) {
+        timer.close();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::permissions::Permissions;
+    use crate::test_util::{ASSERT, run_script};
+    use std::time::{Duration, Instant};
+
+    #[tokio::test]
+    async fn test_timers_fire_in_order() {
+        let source = format!(
+            r#"{ASSERT}
+            const events = [];
+            setTimeout(() => events.push("timeout 20"), 20);
+            setTimeout((a, b) => events.push(`timeout 0 ${{a}} ${{b}}`), 0, "with", "args");
+            queueMicrotask(() => events.push("microtask"));
+            Promise.resolve().then(() => events.push("promise"));
+            events.push("sync");
+
+            let ticks = 0;
+            const interval = setInterval(() => {{
+                ticks += 1;
+                events.push(`tick ${{ticks}}`);
+                if (ticks === 3) {{
+                    clearInterval(interval);
+                }}
+            }}, 10);
+
+            setTimeout(() => {{
+                assertEquals(events.slice(0, 4), ["sync", "microtask", "promise", "timeout 0 with args"]);
+                assertEquals(events.filter((event) => event.startsWith("tick")), ["tick 1", "tick 2", "tick 3"]);
+                assertEquals(events.includes("timeout 20"), true);
+            }}, 80);
+            "#
+        );
+
+        let start = Instant::now();
+        run_script("timers_order.js", &source, Permissions::default())
+            .await
+            .unwrap();
+        // The event loop waited for the last timer.
+        assert!(start.elapsed() >= Duration::from_millis(80));
+    }
+
+    #[tokio::test]
+    async fn test_cleared_timers_do_not_fire_or_keep_the_loop_alive() {
+        let source = r#"
+            const timeout = setTimeout(() => { throw new Error("cleared timeout fired"); }, 60_000);
+            const interval = setInterval(() => { throw new Error("cleared interval fired"); }, 60_000);
+            clearTimeout(timeout);
+            clearInterval(interval);
+            clearTimeout(timeout);
+            clearTimeout(12345);
+            clearTimeout(undefined);
+        "#;
+
+        let start = Instant::now();
+        run_script("timers_cleared.js", source, Permissions::default())
+            .await
+            .unwrap();
+        assert!(start.elapsed() < Duration::from_secs(10));
+    }
+
+    #[tokio::test]
+    async fn test_timer_errors_fail_the_run() {
+        let source = r#"setTimeout(() => { throw new Error("boom"); }, 0);"#;
+
+        let error = run_script("timers_error.js", source, Permissions::default())
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("boom"), "{error}");
+        assert!(!error.to_string().contains("(in promise)"), "{error}");
+    }
+
+    #[tokio::test]
+    async fn test_interval_errors_stop_the_interval() {
+        let source = r#"setInterval(() => { throw new Error("boom"); }, 10);"#;
+
+        let start = Instant::now();
+        let error = run_script("timers_interval_error.js", source, Permissions::default())
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("boom"), "{error}");
+        assert!(start.elapsed() < Duration::from_secs(10));
+    }
+}
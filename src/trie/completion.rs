@@ -0,0 +1,171 @@
+// Implementing a weighted autocomplete index
+
+// This is a `RadixTrie` where every word carries its score as value. The trie caches the best score found anywhere
+// below each node, which lets `top_k` walk the most promising branches first and stop as soon as it has k words,
+// instead of enumerating every completion of the prefix.
+
+use super::radix::{KeyNormalization, RadixTrie};
+
+#[derive(Debug, Default)]
+pub struct CompletionIndex {
+    trie: RadixTrie<u64>,
+}
+
+impl CompletionIndex {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Normalizes words and prefixes before using them, completions are returned as they were inserted.
+    pub fn with_normalization(mut self, normalization: KeyNormalization) -> Self {
+        self.trie = self.trie.with_normalization(normalization);
+        self
+    }
+
+    /// Inserts a word with the given score, replacing the score if the word is already present.
+    pub fn insert(&mut self, word: &str, score: u64) {
+        self.trie.insert_value(word, score);
+    }
+
+    /// Removes a word, returning its score if it was present.
+    pub fn remove(&mut self, word: &str) -> Option<u64> {
+        self.trie.remove(word)
+    }
+
+    pub fn score(&self, word: &str) -> Option<u64> {
+        self.trie.get(word).copied()
+    }
+
+    /// Returns up to `k` words starting with `prefix`, highest score first. Words with the same score are
+    /// returned in lexicographic order.
+    pub fn top_k(&self, prefix: &str, k: usize) -> Vec<(String, u64)> {
+        self.trie
+            .top_k(prefix, k)
+            .into_iter()
+            .map(|(word, score)| (word, *score))
+            .collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.trie.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.trie.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample_index() -> CompletionIndex {
+        let mut index = CompletionIndex::new();
+        index.insert("hello", 10);
+        index.insert("help", 40);
+        index.insert("helmet", 25);
+        index.insert("hell", 5);
+        index.insert("world", 100);
+        index.insert("he", 1);
+        index
+    }
+
+    #[test]
+    fn test_top_k() {
+        let index = sample_index();
+
+        assert_eq!(
+            index.top_k("hel", 3),
+            vec![
+                ("help".to_string(), 40),
+                ("helmet".to_string(), 25),
+                ("hello".to_string(), 10)
+            ]
+        );
+        assert_eq!(index.top_k("", 1), vec![("world".to_string(), 100)]);
+        assert_eq!(index.top_k("he", 10).len(), 5);
+        assert!(index.top_k("x", 3).is_empty());
+        assert!(index.top_k("hel", 0).is_empty());
+    }
+
+    #[test]
+    fn test_top_k_prefix_inside_edge() {
+        let index = sample_index();
+
+        assert_eq!(index.top_k("wor", 2), vec![("world".to_string(), 100)]);
+        assert_eq!(index.top_k("helm", 2), vec![("helmet".to_string(), 25)]);
+        assert!(index.top_k("worx", 2).is_empty());
+    }
+
+    #[test]
+    fn test_update_score() {
+        let mut index = sample_index();
+        index.insert("help", 2);
+        index.insert("hell", 50);
+
+        assert_eq!(index.score("help"), Some(2));
+        assert_eq!(index.score("hel"), None);
+        assert_eq!(
+            index.top_k("hel", 2),
+            vec![("hell".to_string(), 50), ("helmet".to_string(), 25)]
+        );
+    }
+
+    #[test]
+    fn test_remove() {
+        let mut index = sample_index();
+        assert_eq!(index.top_k("", 1), vec![("world".to_string(), 100)]);
+
+        // The cached best scores are reset along the path of the removed word.
+        assert_eq!(index.remove("world"), Some(100));
+        assert_eq!(index.remove("world"), None);
+        assert_eq!(index.top_k("", 1), vec![("help".to_string(), 40)]);
+        assert_eq!(index.remove("help"), Some(40));
+        assert_eq!(
+            index.top_k("hel", 2),
+            vec![("helmet".to_string(), 25), ("hello".to_string(), 10)]
+        );
+        assert_eq!(index.len(), 4);
+    }
+
+    #[test]
+    fn test_normalization() {
+        let mut index = CompletionIndex::new().with_normalization(KeyNormalization::CaseFold);
+        index.insert("Hello", 10);
+        index.insert("HELP", 40);
+        index.insert("hello", 20);
+
+        assert_eq!(index.score("HELLO"), Some(20));
+        assert_eq!(
+            index.top_k("HEL", 3),
+            vec![("HELP".to_string(), 40), ("Hello".to_string(), 20)]
+        );
+    }
+
+    #[test]
+    fn test_empty_word() {
+        let mut index = sample_index();
+        assert_eq!(index.score(""), None);
+
+        index.insert("", 3);
+        assert_eq!(index.score(""), Some(3));
+        assert_eq!(index.top_k("", 10)[5], ("".to_string(), 3));
+    }
+
+    #[test]
+    fn test_ties_are_lexicographic() {
+        let mut index = CompletionIndex::new();
+        index.insert("bb", 7);
+        index.insert("ab", 7);
+        index.insert("b", 7);
+
+        assert_eq!(
+            index.top_k("", 3),
+            vec![
+                ("ab".to_string(), 7),
+                ("b".to_string(), 7),
+                ("bb".to_string(), 7)
+            ]
+        );
+    }
+}
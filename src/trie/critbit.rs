@@ -0,0 +1,298 @@
+// Implementing a crit-bit tree
+
+// A crit-bit tree (https://cr.yp.to/critbit.html) is a binary radix trie over the bits of the keys.
+// Internal nodes only store the position of the first bit where the keys below them differ (the critical bit),
+// as a byte index and a mask, and the keys themselves are only stored in the leaves.
+//
+// Keys are compared as if they were followed by an end marker that sorts before every byte. To make that marker
+// distinct from a 0 byte, every byte is shifted up by one and the marker is 0, so the masks are 9 bits wide.
+
+#[derive(Debug, Clone)]
+enum CritBitNode {
+    Leaf(String),
+    Internal(Box<CritBitInternal>),
+}
+
+#[derive(Debug, Clone)]
+struct CritBitInternal {
+    byte: usize,
+    mask: u16,
+    children: [CritBitNode; 2],
+}
+
+impl CritBitInternal {
+    fn direction(&self, key: &[u8]) -> usize {
+        (key_byte(key, self.byte) & self.mask != 0) as usize
+    }
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct CritBitTree {
+    root: Option<CritBitNode>,
+    len: usize,
+}
+
+impl CritBitTree {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Inserts a key, returning whether it wasn't in the tree yet.
+    pub fn insert(&mut self, key: &str) -> bool {
+        let Some(root) = &mut self.root else {
+            self.root = Some(CritBitNode::Leaf(key.to_string()));
+            self.len += 1;
+            return true;
+        };
+
+        // Find the critical bit between the new key and the closest existing one.
+        let closest = find_leaf(root, key.as_bytes());
+        let Some((byte, mask)) = critical_bit(key.as_bytes(), closest.as_bytes()) else {
+            return false;
+        };
+        let direction = (key_byte(key.as_bytes(), byte) & mask != 0) as usize;
+
+        // Walk down again until the critical bits below are after the new one, and insert the new node there.
+        let mut current = root;
+        loop {
+            let next = match current {
+                CritBitNode::Internal(internal)
+                    if internal.byte < byte || (internal.byte == byte && internal.mask > mask) =>
+                {
+                    internal.direction(key.as_bytes())
+                }
+                _ => break,
+            };
+            let CritBitNode::Internal(internal) = current else {
+                unreachable!("matched as an internal node just above");
+            };
+            current = &mut internal.children[next];
+        }
+
+        let existing = std::mem::replace(current, CritBitNode::Leaf(String::new()));
+        let leaf = CritBitNode::Leaf(key.to_string());
+        let children = if direction == 0 {
+            [leaf, existing]
+        } else {
+            [existing, leaf]
+        };
+        *current = CritBitNode::Internal(Box::new(CritBitInternal {
+            byte,
+            mask,
+            children,
+        }));
+
+        self.len += 1;
+        true
+    }
+
+    pub fn contains(&self, key: &str) -> bool {
+        match &self.root {
+            Some(root) => find_leaf(root, key.as_bytes()) == key,
+            None => false,
+        }
+    }
+
+    /// Deletes a key, returning whether it was in the tree.
+    pub fn delete(&mut self, key: &str) -> bool {
+        let Some(root) = &mut self.root else {
+            return false;
+        };
+
+        // The root is the only node without a parent, so it's handled on its own.
+        if let CritBitNode::Leaf(leaf) = root {
+            if leaf != key {
+                return false;
+            }
+            self.root = None;
+            self.len -= 1;
+            return true;
+        }
+
+        // Otherwise the leaf is replaced, along with its parent, by its sibling.
+        let mut current = root;
+        loop {
+            let CritBitNode::Internal(internal) = &*current else {
+                unreachable!("leaves are only reached through their parent");
+            };
+            let direction = internal.direction(key.as_bytes());
+            match &internal.children[direction] {
+                CritBitNode::Leaf(leaf) if leaf != key => return false,
+                CritBitNode::Leaf(_) => break,
+                CritBitNode::Internal(_) => {
+                    let CritBitNode::Internal(internal) = current else {
+                        unreachable!("matched as an internal node just above");
+                    };
+                    current = &mut internal.children[direction];
+                }
+            }
+        }
+
+        let parent = std::mem::replace(current, CritBitNode::Leaf(String::new()));
+        let CritBitNode::Internal(parent) = parent else {
+            unreachable!("the parent of a leaf is an internal node");
+        };
+        let direction = parent.direction(key.as_bytes());
+        let [left, right] = parent.children;
+        *current = if direction == 0 { right } else { left };
+        self.len -= 1;
+        true
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Iterates over all the keys in lexicographic order.
+    pub fn iter(&self) -> Iter<'_> {
+        Iter {
+            stack: self.root.iter().collect(),
+        }
+    }
+
+    /// Iterates over the keys starting with `prefix`, in lexicographic order.
+    pub fn iter_prefix(&self, prefix: &str) -> Iter<'_> {
+        let Some(mut current) = self.root.as_ref() else {
+            return Iter { stack: Vec::new() };
+        };
+
+        // Every key below a node whose critical bit lies past the prefix agrees on the prefix.
+        while let CritBitNode::Internal(internal) = current {
+            if internal.byte >= prefix.len() {
+                break;
+            }
+            current = &internal.children[internal.direction(prefix.as_bytes())];
+        }
+
+        // So checking a single key of the subtree is enough.
+        if !find_leaf(current, prefix.as_bytes()).starts_with(prefix) {
+            return Iter { stack: Vec::new() };
+        }
+        Iter {
+            stack: vec![current],
+        }
+    }
+}
+
+/// Iterator over the keys of a `CritBitTree`, in lexicographic order.
+pub struct Iter<'a> {
+    stack: Vec<&'a CritBitNode>,
+}
+
+impl<'a> Iterator for Iter<'a> {
+    type Item = &'a str;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            match self.stack.pop()? {
+                CritBitNode::Leaf(key) => return Some(key),
+                CritBitNode::Internal(internal) => {
+                    // Push the right child first so the left one comes out first.
+                    self.stack.push(&internal.children[1]);
+                    self.stack.push(&internal.children[0]);
+                }
+            }
+        }
+    }
+}
+
+// Byte `index` of the key, shifted up by one, or 0 past the end of the key.
+fn key_byte(key: &[u8], index: usize) -> u16 {
+    key.get(index).map_or(0, |byte| *byte as u16 + 1)
+}
+
+// Follows the key's bits down to a leaf. The leaf holds the key if it's in the tree, otherwise it's the key
+// sharing the longest prefix of critical bits with it.
+fn find_leaf<'a>(mut node: &'a CritBitNode, key: &[u8]) -> &'a str {
+    loop {
+        match node {
+            CritBitNode::Leaf(leaf) => return leaf,
+            CritBitNode::Internal(internal) => node = &internal.children[internal.direction(key)],
+        }
+    }
+}
+
+// Finds the first bit where two keys differ, as a byte index and the mask of the bit in that byte.
+fn critical_bit(key_a: &[u8], key_b: &[u8]) -> Option<(usize, u16)> {
+    (0..=key_a.len().max(key_b.len())).find_map(|index| {
+        let difference = key_byte(key_a, index) ^ key_byte(key_b, index);
+        if difference == 0 {
+            return None;
+        }
+        // Keep only the highest differing bit.
+        Some((index, 1 << (15 - difference.leading_zeros())))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_insert_contains_delete() {
+        let mut tree = CritBitTree::new();
+        assert!(tree.insert("hello"));
+        assert!(tree.insert("hell"));
+        assert!(tree.insert("world"));
+        assert!(!tree.insert("hello"));
+        assert_eq!(tree.len(), 3);
+
+        assert!(tree.contains("hello"));
+        assert!(tree.contains("hell"));
+        assert!(!tree.contains("hel"));
+        assert!(!tree.contains("helloo"));
+
+        assert!(tree.delete("hell"));
+        assert!(!tree.delete("hell"));
+        assert!(!tree.contains("hell"));
+        assert!(tree.contains("hello"));
+
+        assert!(tree.delete("hello"));
+        assert!(tree.delete("world"));
+        assert!(tree.is_empty());
+        assert!(!tree.contains("world"));
+    }
+
+    #[test]
+    fn test_ordered_iteration() {
+        let mut tree = CritBitTree::new();
+        for key in [
+            "wow", "hi", "hello", "", "hell", "a\0", "a", "world", "日本",
+        ] {
+            tree.insert(key);
+        }
+
+        assert_eq!(
+            tree.iter().collect::<Vec<_>>(),
+            vec![
+                "", "a", "a\0", "hell", "hello", "hi", "world", "wow", "日本"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_iter_prefix() {
+        let mut tree = CritBitTree::new();
+        for key in ["wow", "hi", "hello", "hell", "world", "help"] {
+            tree.insert(key);
+        }
+
+        assert_eq!(
+            tree.iter_prefix("hel").collect::<Vec<_>>(),
+            vec!["hell", "hello", "help"]
+        );
+        assert_eq!(
+            tree.iter_prefix("w").collect::<Vec<_>>(),
+            vec!["world", "wow"]
+        );
+        assert_eq!(tree.iter_prefix("hello").collect::<Vec<_>>(), vec!["hello"]);
+        assert_eq!(tree.iter_prefix("").count(), 6);
+        assert_eq!(tree.iter_prefix("x").count(), 0);
+        assert_eq!(tree.iter_prefix("helloo").count(), 0);
+        assert_eq!(CritBitTree::new().iter_prefix("a").count(), 0);
+    }
+}
@@ -0,0 +1,280 @@
+// Implementing a double-array trie
+
+// A double-array trie stores the transitions of a byte-wise trie in two flat arrays, `base` and `check`.
+// The child of state `s` on byte `c` lives at `t = base[s] + code(c)`, and is only valid if `check[t] == s`.
+// There is no hashing and no per-node allocation, which makes it a good fit for static dictionaries.
+
+use super::radix::RadixTrie;
+use std::collections::BTreeSet;
+
+// Marks a slot in `check` that doesn't belong to any state yet.
+const EMPTY: u32 = u32::MAX;
+
+// The root always lives in the first slot.
+const ROOT: usize = 0;
+
+#[derive(Debug, Clone)]
+pub struct DoubleArrayTrie {
+    base: Vec<u32>,
+    check: Vec<u32>,
+    is_terminal: Vec<bool>,
+    len: usize,
+}
+
+impl DoubleArrayTrie {
+    /// Builds the trie from a list of words, which is cheapest when they're already sorted in byte order. Duplicated
+    /// words are only stored once.
+    pub fn from_sorted_words<S: AsRef<str>>(words: &[S]) -> Self {
+        // Building a state expects the word ending there to come before the ones going through it.
+        let mut words: Vec<&[u8]> = words.iter().map(|w| w.as_ref().as_bytes()).collect();
+        words.sort_unstable();
+        words.dedup();
+
+        let mut trie = Self {
+            base: vec![0],
+            check: vec![ROOT as u32],
+            is_terminal: vec![false],
+            len: words.len(),
+        };
+        let mut builder = Builder::default();
+        builder.build_state(&mut trie, ROOT, &words, 0);
+        trie.shrink();
+        trie
+    }
+
+    /// Builds the trie from all the keys currently stored in a `RadixTrie`. With a `KeyNormalization`, words have to
+    /// be normalized the same way before being looked up.
+    pub fn from_radix<V>(trie: &RadixTrie<V>) -> Self {
+        let keys: Vec<String> = trie.keys().collect();
+        Self::from_sorted_words(&keys)
+    }
+
+    pub fn contains(&self, word: &str) -> bool {
+        let mut state = ROOT;
+        for byte in word.bytes() {
+            match self.transition(state, byte) {
+                Some(next) => state = next,
+                None => return false,
+            }
+        }
+        self.is_terminal[state]
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    fn transition(&self, state: usize, byte: u8) -> Option<usize> {
+        let next = self.base[state] as usize + code(byte);
+        if next < self.check.len() && self.check[next] == state as u32 {
+            Some(next)
+        } else {
+            None
+        }
+    }
+
+    fn ensure_capacity(&mut self, len: usize) {
+        if self.check.len() < len {
+            self.base.resize(len, 0);
+            self.check.resize(len, EMPTY);
+            self.is_terminal.resize(len, false);
+        }
+    }
+
+    // Drop the unused slots at the end of the arrays, left over from growing them in chunks.
+    fn shrink(&mut self) {
+        let used = self
+            .check
+            .iter()
+            .rposition(|&c| c != EMPTY)
+            .map_or(1, |i| i + 1);
+        self.base.truncate(used);
+        self.check.truncate(used);
+        self.is_terminal.truncate(used);
+        self.base.shrink_to_fit();
+        self.check.shrink_to_fit();
+        self.is_terminal.shrink_to_fit();
+    }
+}
+
+// Byte values are shifted by one so that no transition can ever point back at the root.
+fn code(byte: u8) -> usize {
+    byte as usize + 1
+}
+
+#[derive(Default)]
+struct Builder {
+    // Slots that are still free, so that finding a base doesn't have to walk over the taken ones.
+    free: BTreeSet<usize>,
+}
+
+impl Builder {
+    // `words` are all the (sorted) words sharing the `depth` bytes long prefix that leads to `state`.
+    fn build_state(
+        &mut self,
+        trie: &mut DoubleArrayTrie,
+        state: usize,
+        words: &[&[u8]],
+        depth: usize,
+    ) {
+        // Since the words are sorted, the one ending here (if any) comes first.
+        let words = match words.first() {
+            Some(word) if word.len() == depth => {
+                trie.is_terminal[state] = true;
+                &words[1..]
+            }
+            _ => words,
+        };
+        if words.is_empty() {
+            return;
+        }
+
+        // Group the remaining words by their next byte.
+        let mut groups: Vec<(u8, &[&[u8]])> = Vec::new();
+        let mut start = 0;
+        for i in 1..=words.len() {
+            if i == words.len() || words[i][depth] != words[start][depth] {
+                groups.push((words[start][depth], &words[start..i]));
+                start = i;
+            }
+        }
+
+        let base = self.find_base(trie, &groups);
+        trie.base[state] = base as u32;
+        for (byte, _) in &groups {
+            trie.check[base + code(*byte)] = state as u32;
+            self.free.remove(&(base + code(*byte)));
+        }
+
+        for (byte, group) in groups {
+            self.build_state(trie, base + code(byte), group, depth + 1);
+        }
+    }
+
+    // Find the smallest base for which every child slot is still free.
+    fn find_base(&mut self, trie: &mut DoubleArrayTrie, groups: &[(u8, &[&[u8]])]) -> usize {
+        let first_code = code(groups[0].0);
+        let last_code = code(groups[groups.len() - 1].0);
+
+        // Try every free slot for the first child, the other children then either fit or they don't.
+        let found = self.free.range(first_code..).find_map(|&position| {
+            let base = position - first_code;
+            let fits = base + last_code < trie.check.len()
+                && groups
+                    .iter()
+                    .all(|(byte, _)| trie.check[base + code(*byte)] == EMPTY);
+            fits.then_some(base)
+        });
+        if let Some(base) = found {
+            return base;
+        }
+
+        // Nothing fits, so grow the arrays and place the children in the new space.
+        let old_len = trie.check.len();
+        let base = old_len.max(first_code) - first_code;
+        trie.ensure_capacity(base + last_code + 1 + 256);
+        self.free.extend(old_len..trie.check.len());
+        base
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::trie::radix::{KeyNormalization, SplitMode};
+
+    #[test]
+    fn test_contains() {
+        let trie =
+            DoubleArrayTrie::from_sorted_words(&["hell", "hello", "hi", "win", "world", "wow"]);
+
+        assert!(trie.contains("hello"));
+        assert!(trie.contains("hell"));
+        assert!(trie.contains("wow"));
+        assert!(!trie.contains("he"));
+        assert!(!trie.contains("helloo"));
+        assert!(!trie.contains("x"));
+        assert!(!trie.contains(""));
+        assert_eq!(trie.len(), 6);
+    }
+
+    #[test]
+    fn test_empty_word() {
+        let trie = DoubleArrayTrie::from_sorted_words(&["", "a"]);
+        assert!(trie.contains(""));
+        assert!(trie.contains("a"));
+        assert_eq!(trie.len(), 2);
+
+        let trie = DoubleArrayTrie::from_sorted_words::<&str>(&[]);
+        assert!(!trie.contains(""));
+        assert!(trie.is_empty());
+    }
+
+    #[test]
+    fn test_duplicates_and_unicode() {
+        let trie = DoubleArrayTrie::from_sorted_words(&["café", "café", "cafés", "日本", "日本語"]);
+
+        assert!(trie.contains("café"));
+        assert!(trie.contains("日本語"));
+        assert!(!trie.contains("caf"));
+        assert!(!trie.contains("日"));
+        assert_eq!(trie.len(), 4);
+    }
+
+    #[test]
+    fn test_unsorted_words() {
+        let trie =
+            DoubleArrayTrie::from_sorted_words(&["world", "hello", "hell", "world", "", "hello"]);
+
+        assert_eq!(trie.len(), 4);
+        for word in ["world", "hello", "hell", ""] {
+            assert!(trie.contains(word), "{word}");
+        }
+        assert!(!trie.contains("hel"));
+        assert!(!trie.contains("worlds"));
+    }
+
+    #[test]
+    fn test_from_radix() {
+        let mut radix = RadixTrie::new();
+        for word in ["testing", "tester", "test", "team"] {
+            radix.insert(word);
+        }
+
+        let trie = DoubleArrayTrie::from_radix(&radix);
+        for word in ["testing", "tester", "test", "team"] {
+            assert!(trie.contains(word));
+        }
+        assert!(!trie.contains("tes"));
+        assert_eq!(trie.len(), 4);
+    }
+
+    #[test]
+    fn test_from_normalized_radix() {
+        for words in [["hello", "Help"], ["help", "Hello"]] {
+            let mut radix = RadixTrie::new().with_normalization(KeyNormalization::CaseFold);
+            for word in words {
+                radix.insert(word);
+            }
+
+            let trie = DoubleArrayTrie::from_radix(&radix);
+            assert!(trie.contains("hello"));
+            assert!(trie.contains("help"));
+            assert!(!trie.contains("Hello"));
+            assert!(!trie.contains("Help"));
+        }
+
+        let mut radix = RadixTrie::new().with_split_mode(SplitMode::Grapheme);
+        for word in ["e", "e日", "e\u{301}"] {
+            radix.insert(word);
+        }
+        let trie = DoubleArrayTrie::from_radix(&radix);
+        assert!(trie.contains("e日"));
+        assert!(trie.contains("e\u{301}"));
+        assert_eq!(trie.len(), 3);
+    }
+}
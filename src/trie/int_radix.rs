@@ -0,0 +1,376 @@
+// Implementing a radix tree over fixed-width integer keys
+
+// This is a multibit trie, like the Linux kernel's radix tree or an IP routing table. Each level consumes `stride`
+// bits of the key, so a node has 2^stride child slots. Entries are prefixes `(value, prefix_len)` where only the top
+// `prefix_len` bits of `value` matter. A prefix is stored in the node reached by its complete strides, so a node at
+// depth `d` holds the prefixes whose length is between `d * stride` and `(d + 1) * stride - 1`, and full length
+// keys live one level below the last stride.
+
+use std::fmt::Debug;
+
+/// Integers usable as keys of an `IntRadixTree`.
+pub trait RadixKey: Copy + Ord + Debug {
+    const BITS: u8;
+
+    fn to_u128(self) -> u128;
+
+    fn from_u128(value: u128) -> Self;
+}
+
+macro_rules! impl_radix_key {
+    ($($ty:ty),*) => {
+        $(
+            impl RadixKey for $ty {
+                const BITS: u8 = <$ty>::BITS as u8;
+
+                fn to_u128(self) -> u128 {
+                    self as u128
+                }
+
+                fn from_u128(value: u128) -> Self {
+                    value as $ty
+                }
+            }
+        )*
+    };
+}
+
+impl_radix_key!(u32, u64, u128);
+
+/// How many bits of the key each level of the tree consumes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stride {
+    Four = 4,
+    Eight = 8,
+}
+
+#[derive(Debug, Clone)]
+struct IntRadixNode<V> {
+    // Either empty, or one slot per possible chunk of the key.
+    children: Vec<Option<Box<IntRadixNode<V>>>>,
+    // Prefixes ending within this node, as `(value, prefix_len, item)` sorted by value then length.
+    prefixes: Vec<(u128, u8, V)>,
+}
+
+impl<V> Default for IntRadixNode<V> {
+    fn default() -> Self {
+        Self {
+            children: Vec::new(),
+            prefixes: Vec::new(),
+        }
+    }
+}
+
+impl<V> IntRadixNode<V> {
+    fn is_empty(&self) -> bool {
+        self.prefixes.is_empty() && self.children.iter().all(Option::is_none)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct IntRadixTree<K: RadixKey, V> {
+    root: IntRadixNode<V>,
+    stride: Stride,
+    len: usize,
+    _key: std::marker::PhantomData<K>,
+}
+
+impl<K: RadixKey, V> IntRadixTree<K, V> {
+    pub fn new(stride: Stride) -> Self {
+        Self {
+            root: IntRadixNode::default(),
+            stride,
+            len: 0,
+            _key: std::marker::PhantomData,
+        }
+    }
+
+    /// Inserts an item for the prefix made of the top `prefix_len` bits of `value`, returning the item it replaces.
+    ///
+    /// Panics if `prefix_len` is longer than the key.
+    pub fn insert(&mut self, value: K, prefix_len: u8, item: V) -> Option<V> {
+        assert!(prefix_len <= K::BITS, "prefix is longer than the key");
+        let value = mask::<K>(value.to_u128(), prefix_len);
+        let stride = self.stride as u8;
+
+        let mut current = &mut self.root;
+        for depth in 0..prefix_len / stride {
+            if current.children.is_empty() {
+                current.children.resize_with(1 << stride, || None);
+            }
+            let index = chunk::<K>(value, depth, stride);
+            current = current.children[index].get_or_insert_with(Default::default);
+        }
+
+        match current
+            .prefixes
+            .binary_search_by_key(&(value, prefix_len), |(value, len, _)| (*value, *len))
+        {
+            Ok(index) => Some(std::mem::replace(&mut current.prefixes[index].2, item)),
+            Err(index) => {
+                current.prefixes.insert(index, (value, prefix_len, item));
+                self.len += 1;
+                None
+            }
+        }
+    }
+
+    /// Returns the item stored for exactly this prefix.
+    pub fn get(&self, value: K, prefix_len: u8) -> Option<&V> {
+        if prefix_len > K::BITS {
+            return None;
+        }
+        let value = mask::<K>(value.to_u128(), prefix_len);
+        let stride = self.stride as u8;
+
+        let mut current = &self.root;
+        for depth in 0..prefix_len / stride {
+            let index = chunk::<K>(value, depth, stride);
+            current = current.children.get(index)?.as_ref()?;
+        }
+
+        current
+            .prefixes
+            .iter()
+            .find(|(prefix, len, _)| *prefix == value && *len == prefix_len)
+            .map(|(_, _, item)| item)
+    }
+
+    /// Removes the item stored for exactly this prefix.
+    pub fn remove(&mut self, value: K, prefix_len: u8) -> Option<V> {
+        if prefix_len > K::BITS {
+            return None;
+        }
+        let value = mask::<K>(value.to_u128(), prefix_len);
+        let item = remove_prefix::<K, V>(&mut self.root, value, prefix_len, 0, self.stride as u8);
+        if item.is_some() {
+            self.len -= 1;
+        }
+        item
+    }
+
+    /// Finds the longest stored prefix matching the key, as `(value, prefix_len, item)`.
+    pub fn longest_match(&self, key: K) -> Option<(K, u8, &V)> {
+        let key = key.to_u128();
+        let stride = self.stride as u8;
+        let mut best = None;
+
+        let mut current = &self.root;
+        let mut depth = 0;
+        loop {
+            // Prefixes in a node are sorted by value, the longest match in it isn't necessarily the last one found.
+            for (value, len, item) in &current.prefixes {
+                let is_longer = best.is_none_or(|(_, best_len, _)| *len > best_len);
+                if is_longer && mask::<K>(key, *len) == *value {
+                    best = Some((K::from_u128(*value), *len, item));
+                }
+            }
+
+            if depth >= K::BITS / stride {
+                break;
+            }
+            let index = chunk::<K>(key, depth, stride);
+            match current.children.get(index) {
+                Some(Some(child)) => current = child,
+                _ => break,
+            }
+            depth += 1;
+        }
+
+        best
+    }
+
+    /// Iterates over every prefix as `(value, prefix_len, item)`, ordered by value then prefix length.
+    pub fn iter(&self) -> impl Iterator<Item = (K, u8, &V)> {
+        let mut items = Vec::with_capacity(self.len);
+        collect_prefixes::<K, V>(&self.root, 0, self.stride as u8, &mut items);
+        items.into_iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+// Keeps the top `prefix_len` bits of a key and clears the others.
+fn mask<K: RadixKey>(value: u128, prefix_len: u8) -> u128 {
+    if prefix_len == 0 {
+        return 0;
+    }
+    let host_bits = K::BITS - prefix_len;
+    value & !((1u128 << host_bits) - 1)
+}
+
+// The `stride` bits of the key used to pick a child at `depth`.
+fn chunk<K: RadixKey>(value: u128, depth: u8, stride: u8) -> usize {
+    let shift = K::BITS - (depth + 1) * stride;
+    ((value >> shift) & ((1 << stride) - 1)) as usize
+}
+
+fn remove_prefix<K: RadixKey, V>(
+    node: &mut IntRadixNode<V>,
+    value: u128,
+    prefix_len: u8,
+    depth: u8,
+    stride: u8,
+) -> Option<V> {
+    if depth == prefix_len / stride {
+        let index = node
+            .prefixes
+            .iter()
+            .position(|(prefix, len, _)| *prefix == value && *len == prefix_len)?;
+        return Some(node.prefixes.remove(index).2);
+    }
+
+    let index = chunk::<K>(value, depth, stride);
+    let child = node.children.get_mut(index)?.as_mut()?;
+    let item = remove_prefix::<K, V>(child, value, prefix_len, depth + 1, stride);
+
+    // Prune the nodes that no longer lead to any prefix.
+    if child.is_empty() {
+        node.children[index] = None;
+        if node.children.iter().all(Option::is_none) {
+            node.children = Vec::new();
+        }
+    }
+    item
+}
+
+fn collect_prefixes<'a, K: RadixKey, V>(
+    node: &'a IntRadixNode<V>,
+    depth: u8,
+    stride: u8,
+    items: &mut Vec<(K, u8, &'a V)>,
+) {
+    let mut prefixes = node.prefixes.iter().peekable();
+
+    // A prefix comes before the child for the same chunk, since the child only holds longer prefixes.
+    for (index, child) in node.children.iter().enumerate() {
+        while let Some((value, len, item)) =
+            prefixes.next_if(|(value, _, _)| chunk::<K>(*value, depth, stride) <= index)
+        {
+            items.push((K::from_u128(*value), *len, item));
+        }
+        if let Some(child) = child {
+            collect_prefixes::<K, V>(child, depth + 1, stride, items);
+        }
+    }
+
+    for (value, len, item) in prefixes {
+        items.push((K::from_u128(*value), *len, item));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ipv4(a: u8, b: u8, c: u8, d: u8) -> u32 {
+        u32::from_be_bytes([a, b, c, d])
+    }
+
+    fn routing_table(stride: Stride) -> IntRadixTree<u32, &'static str> {
+        let mut table = IntRadixTree::new(stride);
+        table.insert(ipv4(0, 0, 0, 0), 0, "default");
+        table.insert(ipv4(10, 0, 0, 0), 8, "10/8");
+        table.insert(ipv4(10, 1, 0, 0), 16, "10.1/16");
+        table.insert(ipv4(10, 1, 2, 0), 23, "10.1.2/23");
+        table.insert(ipv4(10, 1, 2, 3), 32, "10.1.2.3/32");
+        table.insert(ipv4(192, 168, 0, 0), 18, "192.168/18");
+        table
+    }
+
+    #[test]
+    fn test_longest_match() {
+        for stride in [Stride::Four, Stride::Eight] {
+            let table = routing_table(stride);
+
+            let route = |a, b, c, d| table.longest_match(ipv4(a, b, c, d)).map(|(_, _, r)| *r);
+            assert_eq!(route(10, 1, 2, 3), Some("10.1.2.3/32"));
+            assert_eq!(route(10, 1, 3, 200), Some("10.1.2/23"));
+            assert_eq!(route(10, 1, 4, 0), Some("10.1/16"));
+            assert_eq!(route(10, 200, 0, 1), Some("10/8"));
+            assert_eq!(route(192, 168, 63, 1), Some("192.168/18"));
+            assert_eq!(route(192, 168, 64, 1), Some("default"));
+            assert_eq!(route(8, 8, 8, 8), Some("default"));
+
+            assert_eq!(
+                table.longest_match(ipv4(10, 1, 3, 9)),
+                Some((ipv4(10, 1, 2, 0), 23, &"10.1.2/23"))
+            );
+        }
+    }
+
+    #[test]
+    fn test_insert_get_remove() {
+        let mut table = routing_table(Stride::Eight);
+        assert_eq!(table.len(), 6);
+
+        // Host bits are ignored.
+        assert_eq!(table.get(ipv4(10, 1, 3, 255), 23), Some(&"10.1.2/23"));
+        assert_eq!(table.get(ipv4(10, 1, 2, 0), 24), None);
+        assert_eq!(table.insert(ipv4(10, 9, 9, 9), 8, "ten"), Some("10/8"));
+        assert_eq!(table.len(), 6);
+
+        assert_eq!(table.remove(ipv4(10, 1, 2, 3), 32), Some("10.1.2.3/32"));
+        assert_eq!(table.remove(ipv4(10, 1, 2, 3), 32), None);
+        assert_eq!(table.remove(ipv4(10, 1, 2, 3), 33), None);
+        assert_eq!(
+            table.longest_match(ipv4(10, 1, 2, 3)).map(|(_, _, r)| *r),
+            Some("10.1.2/23")
+        );
+        assert_eq!(table.len(), 5);
+    }
+
+    #[test]
+    fn test_ordered_iteration() {
+        for stride in [Stride::Four, Stride::Eight] {
+            let table = routing_table(stride);
+            let prefixes: Vec<_> = table.iter().map(|(value, len, _)| (value, len)).collect();
+
+            let mut sorted = prefixes.clone();
+            sorted.sort();
+            assert_eq!(prefixes, sorted);
+            assert_eq!(prefixes.len(), 6);
+        }
+    }
+
+    #[test]
+    fn test_u64_ids() {
+        let mut ids = IntRadixTree::new(Stride::Four);
+        for id in [42u64, 7, u64::MAX, 0, 1 << 40] {
+            ids.insert(id, 64, id.to_string());
+        }
+
+        let ordered: Vec<_> = ids.iter().map(|(id, _, _)| id).collect();
+        assert_eq!(ordered, vec![0, 7, 42, 1 << 40, u64::MAX]);
+        assert_eq!(ids.get(42, 64), Some(&"42".to_string()));
+        assert_eq!(ids.longest_match(43), None);
+    }
+
+    #[test]
+    fn test_u128_prefixes() {
+        let documentation = 0x2001_0db8_u128 << 96;
+        let mut table = IntRadixTree::new(Stride::Eight);
+        table.insert(documentation, 32, "2001:db8::/32");
+        table.insert(documentation | 1 << 64, 64, "2001:db8:0:1::/64");
+
+        assert_eq!(
+            table
+                .longest_match(documentation | 1 << 64 | 5)
+                .map(|(_, _, r)| *r),
+            Some("2001:db8:0:1::/64")
+        );
+        assert_eq!(
+            table
+                .longest_match(documentation | 2 << 64)
+                .map(|(_, _, r)| *r),
+            Some("2001:db8::/32")
+        );
+        assert_eq!(table.longest_match(1), None);
+    }
+}
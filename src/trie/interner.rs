@@ -0,0 +1,137 @@
+// Implementing a string interner
+
+// An interner hands out a small integer, a symbol, for each distinct string, so that repeated strings can be stored
+// and compared as integers. Symbols are dense: the n-th distinct string interned gets symbol n, which makes
+// resolving a symbol a simple index into a list. Looking strings up goes through a `RadixTrie` holding the symbol of
+// every interned string, so the strings sharing a prefix can be listed as well.
+
+use super::radix::RadixTrie;
+
+/// The symbol of an interned string. It stays the same for as long as the interner lives.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Symbol(u32);
+
+impl Symbol {
+    pub fn as_u32(self) -> u32 {
+        self.0
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Interner {
+    symbols: RadixTrie<Symbol>,
+    strings: Vec<Box<str>>,
+}
+
+impl Interner {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Returns the symbol of the string, interning it first if it's new.
+    ///
+    /// Panics if more than `u32::MAX` distinct strings are interned.
+    pub fn intern(&mut self, string: &str) -> Symbol {
+        if let Some(&symbol) = self.symbols.get(string) {
+            return symbol;
+        }
+        let symbol = Symbol(u32::try_from(self.strings.len()).expect("too many interned strings"));
+        self.symbols.insert_value(string, symbol);
+        self.strings.push(string.into());
+        symbol
+    }
+
+    /// Returns the symbol of the string, if it has been interned.
+    pub fn get(&self, string: &str) -> Option<Symbol> {
+        self.symbols.get(string).copied()
+    }
+
+    /// Returns the string of a symbol.
+    ///
+    /// Panics if the symbol comes from another interner.
+    pub fn resolve(&self, symbol: Symbol) -> &str {
+        &self.strings[symbol.0 as usize]
+    }
+
+    /// Returns the symbols of every interned string starting with `prefix`, in the lexicographic order of the
+    /// strings.
+    pub fn symbols_with_prefix(&self, prefix: &str) -> Vec<Symbol> {
+        self.symbols
+            .values_with_prefix(prefix)
+            .into_iter()
+            .copied()
+            .collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.strings.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.strings.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_intern_and_resolve() {
+        let mut interner = Interner::new();
+        let click = interner.intern("click");
+        let close = interner.intern("close");
+        let cl = interner.intern("cl");
+
+        assert_eq!(click.as_u32(), 0);
+        assert_eq!(close.as_u32(), 1);
+        assert_eq!(cl.as_u32(), 2);
+        assert_eq!(interner.intern("click"), click);
+        assert_eq!(interner.intern("close"), close);
+        assert_eq!(interner.len(), 3);
+
+        assert_eq!(interner.resolve(click), "click");
+        assert_eq!(interner.resolve(close), "close");
+        assert_eq!(interner.resolve(cl), "cl");
+        assert_eq!(interner.get("close"), Some(close));
+        assert_eq!(interner.get("clo"), None);
+        assert_eq!(interner.get("closed"), None);
+    }
+
+    #[test]
+    fn test_symbols_with_prefix() {
+        let mut interner = Interner::new();
+        for name in ["mouse:up", "key:down", "mouse:down", "mouse", "key:up"] {
+            interner.intern(name);
+        }
+
+        let names = |prefix| {
+            interner
+                .symbols_with_prefix(prefix)
+                .into_iter()
+                .map(|symbol| interner.resolve(symbol))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(names("mouse"), vec!["mouse", "mouse:down", "mouse:up"]);
+        assert_eq!(names("mouse:"), vec!["mouse:down", "mouse:up"]);
+        assert_eq!(names("k"), vec!["key:down", "key:up"]);
+        assert_eq!(names("key:u"), vec!["key:up"]);
+        assert_eq!(names("").len(), 5);
+        assert!(names("touch").is_empty());
+        assert!(names("key:upper").is_empty());
+    }
+
+    #[test]
+    fn test_empty_and_unicode_strings() {
+        let mut interner = Interner::new();
+        let empty = interner.intern("");
+        let japan = interner.intern("日本");
+        let japanese = interner.intern("日本語");
+
+        assert_eq!(interner.intern(""), empty);
+        assert_eq!(interner.resolve(empty), "");
+        assert_eq!(interner.resolve(japanese), "日本語");
+        assert_eq!(interner.symbols_with_prefix("日"), vec![japan, japanese]);
+        assert_eq!(interner.symbols_with_prefix("").len(), 3);
+    }
+}
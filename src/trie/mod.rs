@@ -1,2 +1,9 @@
+pub mod completion;
+pub mod critbit;
+pub mod double_array;
+pub mod int_radix;
+pub mod interner;
 pub mod naive;
+pub mod patricia;
 pub mod radix;
+pub mod router;
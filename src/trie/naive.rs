@@ -9,12 +9,18 @@ struct NaiveTrieNode {
     children: HashMap<char, NaiveTrieNode>,
 }
 
-struct NaiveTrie {
+pub struct NaiveTrie {
     root: NaiveTrieNode,
 }
 
+impl Default for NaiveTrie {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl NaiveTrie {
-    fn new() -> Self {
+    pub fn new() -> Self {
         Self {
             root: NaiveTrieNode {
                 is_terminal: false,
@@ -24,7 +30,7 @@ impl NaiveTrie {
         }
     }
 
-    fn insert(&mut self, word: &str) {
+    pub fn insert(&mut self, word: &str) {
         let mut current = &mut self.root;
         for c in word.chars() {
             current = current.children.entry(c).or_insert(NaiveTrieNode {
@@ -36,7 +42,7 @@ impl NaiveTrie {
         current.is_terminal = true;
     }
 
-    fn search(&self, word: &str) -> bool {
+    pub fn search(&self, word: &str) -> bool {
         let mut current = &self.root;
         for c in word.chars() {
             let next = current.children.get(&c);
@@ -48,39 +54,29 @@ impl NaiveTrie {
         current.is_terminal
     }
 
-    fn delete(&mut self, word: &str) {
-        recursively_delete_node(&mut self.root, word);
+    /// Deletes a word, returning whether it was in the trie.
+    pub fn delete(&mut self, word: &str) -> bool {
+        recursively_delete_node(&mut self.root, word).is_some()
     }
 }
 
-fn recursively_delete_node(node: &mut NaiveTrieNode, word: &str) -> Option<NaiveTrieNode> {
-    if word.is_empty() && node.is_terminal {
-        node.is_terminal = false;
-        if !node.children.is_empty() {
-            return Some(node.clone());
-        }
-        node.value = None;
-        return None;
-    }
-    // Recursively delete the node
-    let next_char = word.chars().next().unwrap();
-
-    let next_node = node.children.get_mut(&next_char).unwrap();
-    let new_node = recursively_delete_node(next_node, &word[1..]);
-    match new_node {
-        None => {
-            node.children.remove(&next_char);
-            if node.children.is_empty() {
-                node.value = None;
-                return None;
-            }
-        }
-        Some(trie_node) => {
-            node.children.insert(next_char, trie_node);
+// Returns `None` if the word isn't in the trie, otherwise whether `node` should now be removed from its parent.
+fn recursively_delete_node(node: &mut NaiveTrieNode, word: &str) -> Option<bool> {
+    let Some(next_char) = word.chars().next() else {
+        if !node.is_terminal {
+            return None;
         }
+        node.is_terminal = false;
+        return Some(node.children.is_empty());
     };
 
-    Some(node.clone())
+    // Recursively delete the node, the word isn't in the trie if there's no node for its next char.
+    let next_node = node.children.get_mut(&next_char)?;
+    if recursively_delete_node(next_node, &word[next_char.len_utf8()..])? {
+        node.children.remove(&next_char);
+    }
+
+    Some(!node.is_terminal && node.children.is_empty())
 }
 
 fn visualize_trie(node: &NaiveTrieNode, prefix: &str, is_last: bool) {
@@ -135,7 +131,46 @@ fn main() {
     visualize_trie(&trie.root, "", true);
 }
 
-// #[cfg(test)]
-// mod tests {
-//     use super::*;
-// }
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_empty_word() {
+        let mut trie = NaiveTrie::new();
+        assert!(!trie.search(""));
+        assert!(!trie.delete(""));
+
+        trie.insert("hello");
+        trie.insert("");
+        assert!(trie.search(""));
+        assert!(trie.search("hello"));
+
+        assert!(trie.delete(""));
+        assert!(!trie.search(""));
+        assert!(trie.search("hello"));
+    }
+
+    #[test]
+    fn test_delete_missing_word() {
+        let mut trie = NaiveTrie::new();
+        trie.insert("hello");
+
+        assert!(!trie.delete("help"));
+        assert!(!trie.delete("hell"));
+        assert!(trie.search("hello"));
+    }
+
+    #[test]
+    fn test_delete_multibyte_word() {
+        let mut trie = NaiveTrie::new();
+        trie.insert("日本");
+        trie.insert("日本語");
+
+        assert!(trie.delete("日本語"));
+        assert!(!trie.search("日本語"));
+        assert!(trie.search("日本"));
+        assert!(trie.delete("日本"));
+        assert!(trie.root.children.is_empty());
+    }
+}
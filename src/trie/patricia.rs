@@ -1,295 +1,729 @@
-// Implementing a radix trie
-
-// Patricia trie is a binary radix trie, with bits as keys instead of string.
+// Implementing a Merkle Patricia trie
+
+// This is the trie Ethereum uses for its state (https://ethereum.org/en/developers/docs/data-structures-and-encoding/patricia-merkle-trie/).
+// Keys are walked nibble by nibble (half bytes), and there are three kinds of nodes:
+// - a leaf holds the rest of a key and its value,
+// - an extension holds a run of nibbles shared by every key below it, and a single child,
+// - a branch has one child per nibble, plus the value of the key ending on it.
+//
+// Every node is RLP encoded, and a node refers to its children by the keccak256 hash of their encoding, unless the
+// encoding is shorter than a hash, in which case it's inlined. The hash of the root then commits to the whole trie,
+// and the nodes on the path to a key are a proof that the key is (or isn't) in the trie.
+
+use sha3::{Digest, Keccak256};
 use std::collections::HashMap;
+use thiserror::Error;
+
+pub type Hash = [u8; 32];
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum ProofError {
+    #[error("Proof is missing the node with hash {0}")]
+    MissingNode(String),
+
+    #[error("Invalid node in proof: {0}")]
+    InvalidNode(&'static str),
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+enum Node {
+    #[default]
+    Empty,
+    Leaf {
+        path: Vec<u8>,
+        value: Vec<u8>,
+    },
+    Extension {
+        path: Vec<u8>,
+        child: Box<Node>,
+    },
+    Branch {
+        children: Box<[Node; 16]>,
+        value: Option<Vec<u8>>,
+    },
+}
+
+impl Node {
+    fn empty_branch() -> (Box<[Node; 16]>, Option<Vec<u8>>) {
+        (Box::default(), None)
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        match self {
+            Node::Empty => encode_bytes(&[]),
+            Node::Leaf { path, value } => {
+                encode_list(&[encode_bytes(&hex_prefix(path, true)), encode_bytes(value)])
+            }
+            Node::Extension { path, child } => {
+                encode_list(&[encode_bytes(&hex_prefix(path, false)), child.reference()])
+            }
+            Node::Branch { children, value } => {
+                let mut items: Vec<Vec<u8>> = children.iter().map(Node::reference).collect();
+                items.push(encode_bytes(value.as_deref().unwrap_or_default()));
+                encode_list(&items)
+            }
+        }
+    }
+
+    // How a parent refers to this node: the encoding itself if it's short enough, its hash otherwise.
+    fn reference(&self) -> Vec<u8> {
+        let encoded = self.encode();
+        if encoded.len() < 32 {
+            encoded
+        } else {
+            encode_bytes(&keccak(&encoded))
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct MerklePatriciaTrie {
+    root: Node,
+    len: usize,
+}
+
+impl MerklePatriciaTrie {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Inserts a value, returning the one it replaces. As in Ethereum, an empty value removes the key.
+    pub fn insert(&mut self, key: &[u8], value: Vec<u8>) -> Option<Vec<u8>> {
+        if value.is_empty() {
+            return self.remove(key);
+        }
+        let root = std::mem::take(&mut self.root);
+        let (root, old) = insert_node(root, &to_nibbles(key), value);
+        self.root = root;
+        if old.is_none() {
+            self.len += 1;
+        }
+        old
+    }
+
+    pub fn get(&self, key: &[u8]) -> Option<&[u8]> {
+        let nibbles = to_nibbles(key);
+        let mut path = nibbles.as_slice();
+        let mut current = &self.root;
+        loop {
+            match current {
+                Node::Empty => return None,
+                Node::Leaf {
+                    path: leaf_path,
+                    value,
+                } => {
+                    return (leaf_path == path).then_some(value.as_slice());
+                }
+                Node::Extension {
+                    path: extension_path,
+                    child,
+                } => {
+                    path = path.strip_prefix(extension_path.as_slice())?;
+                    current = child;
+                }
+                Node::Branch { children, value } => match path.split_first() {
+                    None => return value.as_deref(),
+                    Some((nibble, rest)) => {
+                        current = &children[*nibble as usize];
+                        path = rest;
+                    }
+                },
+            }
+        }
+    }
+
+    pub fn remove(&mut self, key: &[u8]) -> Option<Vec<u8>> {
+        let root = std::mem::take(&mut self.root);
+        let (root, old) = remove_node(root, &to_nibbles(key));
+        self.root = root;
+        if old.is_some() {
+            self.len -= 1;
+        }
+        old
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// The keccak256 hash of the root node, which commits to every key and value in the trie.
+    pub fn root_hash(&self) -> Hash {
+        keccak(&self.root.encode())
+    }
+
+    /// Returns the encoded nodes on the path to the key, starting with the root. Inlined nodes are part of their
+    /// parent's encoding, so only the nodes referred to by hash are listed. The proof works both to show the key is
+    /// in the trie, and to show it isn't.
+    pub fn prove(&self, key: &[u8]) -> Vec<Vec<u8>> {
+        let nibbles = to_nibbles(key);
+        let mut path = nibbles.as_slice();
+        let mut proof = vec![self.root.encode()];
+        let mut current = &self.root;
+        loop {
+            let next = match current {
+                Node::Extension {
+                    path: extension_path,
+                    child,
+                } if path.starts_with(extension_path) => {
+                    path = &path[extension_path.len()..];
+                    child
+                }
+                Node::Branch { children, .. } if !path.is_empty() => {
+                    let child = &children[path[0] as usize];
+                    path = &path[1..];
+                    child
+                }
+                _ => break,
+            };
+
+            let encoded = next.encode();
+            if encoded.len() >= 32 {
+                proof.push(encoded);
+            }
+            current = next;
+        }
+        proof
+    }
+}
+
+/// Checks a proof from `MerklePatriciaTrie::prove` against a root hash. Returns the value of the key if the proof
+/// shows it's in the trie, or `None` if the proof shows it isn't.
+pub fn verify_proof(
+    root: &Hash,
+    key: &[u8],
+    proof: &[Vec<u8>],
+) -> Result<Option<Vec<u8>>, ProofError> {
+    let nodes: HashMap<Hash, &[u8]> = proof
+        .iter()
+        .map(|node| (keccak(node), node.as_slice()))
+        .collect();
+    let lookup = |hash: &[u8]| {
+        nodes
+            .get(hash)
+            .copied()
+            .ok_or_else(|| ProofError::MissingNode(to_hex(hash)))
+    };
+
+    let nibbles = to_nibbles(key);
+    let mut path = nibbles.as_slice();
+    let mut encoded = lookup(root)?;
+    loop {
+        let items = match decode(encoded)? {
+            (Rlp::List(payload), _) => decode_list(payload)?,
+            (Rlp::Bytes([]), _) => return Ok(None),
+            _ => return Err(ProofError::InvalidNode("expected a list")),
+        };
+
+        // Pick the reference to the next node, or return once the key has been settled.
+        let reference = match items.as_slice() {
+            [(Rlp::Bytes(prefix), _), next] if !prefix.is_empty() => {
+                let (node_path, is_leaf) = decode_hex_prefix(prefix)?;
+                if is_leaf {
+                    let (Rlp::Bytes(value), _) = next else {
+                        return Err(ProofError::InvalidNode("leaf value must be a string"));
+                    };
+                    return Ok((node_path == path).then(|| value.to_vec()));
+                }
+                let Some(rest) = path.strip_prefix(node_path.as_slice()) else {
+                    return Ok(None);
+                };
+                path = rest;
+                *next
+            }
+            [children @ .., (Rlp::Bytes(value), _)] if children.len() == 16 => {
+                let Some((nibble, rest)) = path.split_first() else {
+                    return Ok((!value.is_empty()).then(|| value.to_vec()));
+                };
+                path = rest;
+                children[*nibble as usize]
+            }
+            _ => return Err(ProofError::InvalidNode("unexpected number of items")),
+        };
+
+        encoded = match reference {
+            (Rlp::Bytes([]), _) => return Ok(None),
+            (Rlp::Bytes(hash), _) if hash.len() == 32 => lookup(hash)?,
+            (Rlp::List(_), raw) => raw,
+            _ => return Err(ProofError::InvalidNode("invalid child reference")),
+        };
+    }
+}
+
+fn insert_node(node: Node, path: &[u8], value: Vec<u8>) -> (Node, Option<Vec<u8>>) {
+    match node {
+        Node::Empty => (
+            Node::Leaf {
+                path: path.to_vec(),
+                value,
+            },
+            None,
+        ),
+        Node::Leaf {
+            path: leaf_path,
+            value: leaf_value,
+        } => {
+            if leaf_path == path {
+                return (
+                    Node::Leaf {
+                        path: leaf_path,
+                        value,
+                    },
+                    Some(leaf_value),
+                );
+            }
+
+            // The keys diverge, so a branch goes where they do, below an extension for the shared nibbles.
+            let common = common_prefix_len(&leaf_path, path);
+            let (mut children, mut branch_value) = Node::empty_branch();
+            for (key, value) in [(leaf_path.as_slice(), leaf_value), (path, value)] {
+                match key[common..].split_first() {
+                    None => branch_value = Some(value),
+                    Some((nibble, rest)) => {
+                        children[*nibble as usize] = Node::Leaf {
+                            path: rest.to_vec(),
+                            value,
+                        }
+                    }
+                }
+            }
+            let branch = Node::Branch {
+                children,
+                value: branch_value,
+            };
+            (extend(&path[..common], branch), None)
+        }
+        Node::Extension {
+            path: extension_path,
+            child,
+        } => {
+            let common = common_prefix_len(&extension_path, path);
+            if common == extension_path.len() {
+                let (child, old) = insert_node(*child, &path[common..], value);
+                let node = Node::Extension {
+                    path: extension_path,
+                    child: Box::new(child),
+                };
+                return (node, old);
+            }
+
+            // The key leaves the extension halfway, so the extension is split around a new branch.
+            let (mut children, mut branch_value) = Node::empty_branch();
+            children[extension_path[common] as usize] =
+                extend(&extension_path[common + 1..], *child);
+            match path[common..].split_first() {
+                None => branch_value = Some(value),
+                Some((nibble, rest)) => {
+                    children[*nibble as usize] = Node::Leaf {
+                        path: rest.to_vec(),
+                        value,
+                    }
+                }
+            }
+            let branch = Node::Branch {
+                children,
+                value: branch_value,
+            };
+            (extend(&path[..common], branch), None)
+        }
+        Node::Branch {
+            mut children,
+            value: branch_value,
+        } => match path.split_first() {
+            None => (
+                Node::Branch {
+                    children,
+                    value: Some(value),
+                },
+                branch_value,
+            ),
+            Some((nibble, rest)) => {
+                let child = std::mem::take(&mut children[*nibble as usize]);
+                let (child, old) = insert_node(child, rest, value);
+                children[*nibble as usize] = child;
+                let node = Node::Branch {
+                    children,
+                    value: branch_value,
+                };
+                (node, old)
+            }
+        },
+    }
+}
+
+fn remove_node(node: Node, path: &[u8]) -> (Node, Option<Vec<u8>>) {
+    match node {
+        Node::Empty => (Node::Empty, None),
+        Node::Leaf {
+            path: leaf_path,
+            value,
+        } => {
+            if leaf_path == path {
+                (Node::Empty, Some(value))
+            } else {
+                (
+                    Node::Leaf {
+                        path: leaf_path,
+                        value,
+                    },
+                    None,
+                )
+            }
+        }
+        Node::Extension {
+            path: extension_path,
+            child,
+        } => {
+            let Some(rest) = path.strip_prefix(extension_path.as_slice()) else {
+                let node = Node::Extension {
+                    path: extension_path,
+                    child,
+                };
+                return (node, None);
+            };
+            let (child, old) = remove_node(*child, rest);
+            (extend(&extension_path, child), old)
+        }
+        Node::Branch {
+            mut children,
+            value: mut branch_value,
+        } => {
+            let old = match path.split_first() {
+                None => branch_value.take(),
+                Some((nibble, rest)) => {
+                    let child = std::mem::take(&mut children[*nibble as usize]);
+                    let (child, old) = remove_node(child, rest);
+                    children[*nibble as usize] = child;
+                    old
+                }
+            };
+            (collapse_branch(children, branch_value), old)
+        }
+    }
+}
+
+// Prepends nibbles to a node, merging them into its own path when it has one.
+fn extend(prefix: &[u8], node: Node) -> Node {
+    if prefix.is_empty() {
+        return node;
+    }
+    match node {
+        Node::Empty => Node::Empty,
+        Node::Leaf { path, value } => Node::Leaf {
+            path: [prefix, &path].concat(),
+            value,
+        },
+        Node::Extension { path, child } => Node::Extension {
+            path: [prefix, &path].concat(),
+            child,
+        },
+        branch @ Node::Branch { .. } => Node::Extension {
+            path: prefix.to_vec(),
+            child: Box::new(branch),
+        },
+    }
+}
+
+// A branch with a single thing left in it is replaced, so that every trie holding the same keys has the same shape
+// and therefore the same root hash.
+fn collapse_branch(mut children: Box<[Node; 16]>, value: Option<Vec<u8>>) -> Node {
+    let remaining: Vec<usize> = children
+        .iter()
+        .enumerate()
+        .filter(|(_, child)| **child != Node::Empty)
+        .map(|(nibble, _)| nibble)
+        .collect();
+
+    match (remaining.as_slice(), value) {
+        ([], None) => Node::Empty,
+        ([], Some(value)) => Node::Leaf {
+            path: Vec::new(),
+            value,
+        },
+        ([nibble], None) => {
+            let child = std::mem::take(&mut children[*nibble]);
+            extend(&[*nibble as u8], child)
+        }
+        (_, value) => Node::Branch { children, value },
+    }
+}
+
+fn to_nibbles(key: &[u8]) -> Vec<u8> {
+    key.iter()
+        .flat_map(|byte| [byte >> 4, byte & 0x0f])
+        .collect()
+}
+
+fn common_prefix_len(a: &[u8], b: &[u8]) -> usize {
+    a.iter().zip(b).take_while(|(a, b)| a == b).count()
+}
+
+// Packs nibbles into bytes. The first nibble flags whether the node is a leaf and whether the path has an odd
+// length, in which case the first nibble of the path shares the first byte.
+fn hex_prefix(path: &[u8], is_leaf: bool) -> Vec<u8> {
+    let flag = if is_leaf { 2 } else { 0 } + (path.len() % 2) as u8;
+    let mut nibbles = vec![flag];
+    if path.len().is_multiple_of(2) {
+        nibbles.push(0);
+    }
+    nibbles.extend_from_slice(path);
+    nibbles
+        .chunks(2)
+        .map(|pair| (pair[0] << 4) | pair[1])
+        .collect()
+}
+
+fn decode_hex_prefix(bytes: &[u8]) -> Result<(Vec<u8>, bool), ProofError> {
+    let flag = bytes[0] >> 4;
+    if flag > 3 {
+        return Err(ProofError::InvalidNode("invalid path flag"));
+    }
+    let mut nibbles = to_nibbles(bytes);
+    // Drop the flag, and the padding nibble for even paths.
+    let skip = if flag % 2 == 1 { 1 } else { 2 };
+    nibbles.drain(..skip);
+    Ok((nibbles, flag >= 2))
+}
+
+fn keccak(data: &[u8]) -> Hash {
+    Keccak256::digest(data).into()
+}
+
+pub fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+// Just enough RLP (https://ethereum.org/en/developers/docs/data-structures-and-encoding/rlp/) for the trie nodes:
+// byte strings, and lists of already encoded items.
+
+fn encode_bytes(bytes: &[u8]) -> Vec<u8> {
+    if let [byte] = bytes
+        && *byte < 0x80
+    {
+        return vec![*byte];
+    }
+    let mut encoded = encode_length(bytes.len(), 0x80);
+    encoded.extend_from_slice(bytes);
+    encoded
+}
+
+fn encode_list(items: &[Vec<u8>]) -> Vec<u8> {
+    let payload = items.concat();
+    let mut encoded = encode_length(payload.len(), 0xc0);
+    encoded.extend(payload);
+    encoded
+}
+
+fn encode_length(len: usize, offset: u8) -> Vec<u8> {
+    if len < 56 {
+        return vec![offset + len as u8];
+    }
+    let len_bytes = len.to_be_bytes();
+    let first = len_bytes.iter().position(|byte| *byte != 0).unwrap();
+    let mut encoded = vec![offset + 55 + (len_bytes.len() - first) as u8];
+    encoded.extend_from_slice(&len_bytes[first..]);
+    encoded
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Rlp<'a> {
+    Bytes(&'a [u8]),
+    // The payload of the list, still to be decoded.
+    List(&'a [u8]),
+}
 
-#[derive(Debug, Clone)]
-struct PatriciaTrieNode {
-    is_terminal: bool,
-    value: Option<String>,
-    children: HashMap<String, PatriciaTrieNode>,
+// Decodes the first item of `data`, returning it along with its raw encoding.
+fn decode(data: &[u8]) -> Result<(Rlp<'_>, &[u8]), ProofError> {
+    let (&first, rest) = data
+        .split_first()
+        .ok_or(ProofError::InvalidNode("empty RLP"))?;
+
+    let (header_len, payload_len, is_list) = match first {
+        0x00..=0x7f => return Ok((Rlp::Bytes(&data[..1]), &data[..1])),
+        0x80..=0xb7 => (1, (first - 0x80) as usize, false),
+        0xc0..=0xf7 => (1, (first - 0xc0) as usize, true),
+        _ => {
+            let (len_of_len, is_list) = if first < 0xc0 {
+                ((first - 0xb7) as usize, false)
+            } else {
+                ((first - 0xf7) as usize, true)
+            };
+            let len_bytes = rest
+                .get(..len_of_len)
+                .ok_or(ProofError::InvalidNode("truncated RLP"))?;
+            if len_of_len > size_of::<usize>() {
+                return Err(ProofError::InvalidNode("RLP length too large"));
+            }
+            let len = len_bytes
+                .iter()
+                .fold(0usize, |len, byte| (len << 8) | *byte as usize);
+            (1 + len_of_len, len, is_list)
+        }
+    };
+
+    let raw = data
+        .get(..header_len + payload_len)
+        .ok_or(ProofError::InvalidNode("truncated RLP"))?;
+    let payload = &raw[header_len..];
+    let item = if is_list {
+        Rlp::List(payload)
+    } else {
+        Rlp::Bytes(payload)
+    };
+    Ok((item, raw))
 }
 
-struct PatriciaTrie {
-    root: PatriciaTrieNode,
+fn decode_list(mut payload: &[u8]) -> Result<Vec<(Rlp<'_>, &[u8])>, ProofError> {
+    let mut items = Vec::new();
+    while !payload.is_empty() {
+        let (item, raw) = decode(payload)?;
+        payload = &payload[raw.len()..];
+        items.push((item, raw));
+    }
+    Ok(items)
 }
 
-// impl PatriciaTrie {
-//     fn new() -> Self {
-//         Self {
-//             root: PatriciaTrieNode {
-//                 is_terminal: false,
-//                 value: None,
-//                 children: HashMap::new(),
-//             },
-//         }
-//     }
-
-//     fn insert(&mut self, input_word: &str) {
-//         let mut terminal_reached = false;
-
-//         let mut current: &mut PatriciaTrieNode = &mut self.root;
-//         let mut word: String = input_word.to_owned();
-//         while !terminal_reached {
-//             // Find common prefix and potential next node.
-
-//             let next_keys = current.children.keys().find_map(|child_key| {
-//                 let common_prefix = get_common_prefix(&word, child_key);
-//                 if !common_prefix.is_empty() {
-//                     return Some((child_key.to_owned(), common_prefix));
-//                 }
-//                 None
-//             });
-
-//             // With the common prefix and the next node known, we now go through the cases.]
-//             // 1. if no common prefix, insert the node as it is /
-//             // 2. if common prefix == input word length, return immediately (node already exists)
-//             // 3. if common prefix length == value length of the nodes, continue down (continue searching)
-//             // 4. if common prefix not equals, then split the node into its common prefix and its postfix(reorg).
-//             let (next_possible_node, next_key) = match next_keys {
-//                 None => {
-//                     // Case 1: no common prefix, insert new word whole from root.
-//                     current.children.insert(
-//                         word.clone(),
-//                         PatriciaTrieNode {
-//                             is_terminal: true,
-//                             value: Some(word.to_string()),
-//                             children: HashMap::new(),
-//                         },
-//                     );
-//                     break;
-//                 }
-//                 Some((next_possible_node, next_key)) => (next_possible_node, next_key),
-//             };
-
-//             if next_possible_node == word {
-//                 // Case 2: common prefix exists as a node, and its the same as the input, thus the node already exists, and we don't insert.
-//                 terminal_reached = true;
-//             } else if next_key == next_possible_node {
-//                 // Case 3: there's already a node of the prefix, so we continue searching
-//                 let next_node = current.children.get_mut(&next_possible_node).unwrap();
-//                 word = word.strip_prefix(&next_possible_node).unwrap().to_owned();
-//                 current = next_node;
-//             } else {
-//                 // Case 4: There's a common prefix, and an existing node, we split the node and reorg the tree.
-//                 let mut current_next_node = current.children.remove(&next_possible_node).unwrap();
-//                 let current_next_node_new_key = current_next_node
-//                     .value
-//                     .clone()
-//                     .unwrap()
-//                     .strip_prefix(&next_key)
-//                     .unwrap()
-//                     .to_owned();
-//                 current_next_node.value = Some(current_next_node_new_key.clone());
-
-//                 // Special case where the prefix is also the same as the new word inserted.
-//                 // in this case terminate early.
-//                 let is_new_node_terminal = next_key == word;
-
-//                 let mut new_next_node = PatriciaTrieNode {
-//                     children: HashMap::new(),
-//                     is_terminal: is_new_node_terminal,
-//                     value: Some(next_key.clone()),
-//                 };
-//                 new_next_node
-//                     .children
-//                     .insert(current_next_node_new_key, current_next_node.to_owned());
-
-//                 current
-//                     .children
-//                     .insert(next_key.clone(), new_next_node.to_owned());
-
-//                 word = word.strip_prefix(&next_key).unwrap().to_owned();
-//                 current = current.children.get_mut(&next_key).unwrap();
-
-//                 terminal_reached = is_new_node_terminal;
-//             }
-//         }
-//     }
-
-//     fn search(&self, word: &str) -> bool {
-//         let mut terminal_reached = false;
-//         let mut current = &self.root;
-//         let mut current_word = word.to_owned();
-
-//         while !terminal_reached {
-//             let next_key = current.children.keys().find_map(|child_key| {
-//                 let common_prefix = get_common_prefix(&current_word, child_key);
-//                 if !common_prefix.is_empty() {
-//                     return Some(common_prefix);
-//                 }
-//                 None
-//             });
-
-//             let next_key = match next_key {
-//                 None => {
-//                     return false;
-//                 }
-//                 Some(key) => key,
-//             };
-
-//             let next_val = current.children.get(&next_key);
-//             match next_val {
-//                 None => return false,
-//                 Some(val) => {
-//                     if val.value == Some(current_word.clone()) && val.is_terminal {
-//                         terminal_reached = true
-//                     } else {
-//                         // Unwrapping here since there's no chance stripping will fail
-//                         current_word = current_word.strip_prefix(&next_key).unwrap().to_owned();
-//                     }
-//                     current = val;
-//                 }
-//             }
-//         }
-
-//         current.is_terminal
-//     }
-
-//     fn delete(&mut self, word: &str) {
-//         recursively_delete_node(&mut self.root, word);
-//     }
-// }
-
-// fn get_common_prefix(word_a: &str, word_b: &str) -> String {
-//     let mut common_prefix = String::new();
-
-//     if word_a.is_empty() || word_b.is_empty() {
-//         return common_prefix;
-//     }
-
-//     let word_a_vec = word_a.chars().collect::<Vec<char>>();
-//     let word_b_vec = word_b.chars().collect::<Vec<char>>();
-
-//     let (long_vec, short_vec) = if word_a_vec.len() > word_b.len() {
-//         (word_a_vec, word_b_vec)
-//     } else {
-//         (word_b_vec, word_a_vec)
-//     };
-
-//     for (idx, c) in short_vec.into_iter().enumerate() {
-//         if c != long_vec[idx] {
-//             break;
-//         }
-//         common_prefix.push(c);
-//     }
-
-//     common_prefix
-// }
-
-// fn recursively_delete_node(node: &mut PatriciaTrieNode, word: &str) -> Option<PatriciaTrieNode> {
-//     if word.is_empty() && node.is_terminal {
-//         node.is_terminal = false;
-//         if !node.children.is_empty() {
-//             // Special case: if one node children, we consolidate
-//             if node.children.len() == 1 {
-//                 let children_clone = node.children.clone();
-//                 // This definitely exist, since there's only a single child.
-//                 let child = children_clone.iter().next().unwrap();
-//                 // This should always work too, given all child should have value.
-//                 node.value.as_mut().unwrap().push_str(child.0);
-//                 node.children = child.1.children.clone();
-//                 node.is_terminal = child.1.is_terminal;
-//             }
-//             return Some(node.clone());
-//         }
-//         node.value = None;
-//         return None;
-//     }
-//     // Recursively delete the node
-//     let next_key = node
-//         .children
-//         .keys()
-//         .find_map(|child_key: &String| {
-//             let common_prefix = get_common_prefix(word, child_key);
-//             if !common_prefix.is_empty() {
-//                 return Some(common_prefix);
-//             }
-//             None
-//         })
-//         .unwrap();
-
-//     let next_node = node.children.get_mut(&next_key).unwrap();
-//     let next_word = &word[next_key.len()..];
-//     let new_node = recursively_delete_node(next_node, next_word);
-//     match new_node {
-//         None => {
-//             node.children.remove(&next_key);
-//             if node.children.is_empty() {
-//                 node.value = None;
-//                 return None;
-//             }
-//         }
-//         Some(trie_node) => {
-//             let mut actual_next_key = next_key.clone();
-//             // Special case, if node only a single one we can reconsolidate
-//             let trie_node_actual_key = trie_node.value.clone().unwrap();
-//             if trie_node_actual_key != next_key {
-//                 node.children.remove(&next_key);
-//                 actual_next_key = trie_node_actual_key;
-//             }
-
-//             node.children.insert(actual_next_key, trie_node);
-//         }
-//     };
-
-//     Some(node.clone())
-// }
-
-// fn visualize_trie(node: &PatriciaTrieNode, prefix: &str, is_last: bool) {
-//     // Print the current node
-//     let marker = if is_last { "└── " } else { "├── " };
-//     let value = node
-//         .value
-//         .clone()
-//         .map_or("ROOT".to_string(), |c| c.to_string());
-//     let terminal = if node.is_terminal { " (T)" } else { "" };
-//     println!("{}{}{}{}", prefix, marker, value, terminal);
-
-//     // Calculate the new prefix for children
-//     let new_prefix = if is_last {
-//         format!("{}    ", prefix)
-//     } else {
-//         format!("{}│   ", prefix)
-//     };
-
-//     // Sort children for consistent visualization
-//     let mut children: Vec<_> = node.children.iter().collect();
-//     children.sort_by_key(|(c, _)| *c);
-
-//     // Print children
-//     for (i, (_, child)) in children.iter().enumerate() {
-//         let is_last_child = i == children.len() - 1;
-//         visualize_trie(child, &new_prefix, is_last_child);
-//     }
-// }
-
-fn main() {
-    //     let mut trie = PatriciaTrie::new();
-    //     trie.insert("hello");
-    //     trie.insert("hell");
-    //     trie.insert("world");
-    //     trie.insert("hi");
-    //     trie.insert("wow");
-    //     trie.insert("win");
-
-    //     println!("Trie Structure:");
-    //     visualize_trie(&trie.root, "", true);
-
-    //     println!("{:?}", trie.search("hello"));
-    //     println!("{:?}", trie.search("hell"));
-    //     println!("{:?}", trie.search("world"));
-    //     println!("{:?}", trie.search("hi"));
-    //     println!("{:?}", trie.search("win"));
-    //     trie.delete("hell");
-    //     trie.delete("hello");
-    //     println!("{:?}", trie.search("hello"));
-    //     println!("{:?}", trie.search("hell"));
-
-    //     println!("Trie Structure after deletion:");
-    //     visualize_trie(&trie.root, "", true);
-
-    //     trie.delete("hello");
-    //     println!("Trie Structure after deletion:");
-    //     visualize_trie(&trie.root, "", true);
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hash(hex: &str) -> Hash {
+        let bytes: Vec<u8> = (0..hex.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap())
+            .collect();
+        bytes.try_into().unwrap()
+    }
+
+    fn trie_from(entries: &[(&str, &str)]) -> MerklePatriciaTrie {
+        let mut trie = MerklePatriciaTrie::new();
+        for (key, value) in entries {
+            trie.insert(key.as_bytes(), value.as_bytes().to_vec());
+        }
+        trie
+    }
+
+    const PUPPY: [(&str, &str); 4] = [
+        ("do", "verb"),
+        ("horse", "stallion"),
+        ("doge", "coin"),
+        ("dog", "puppy"),
+    ];
+
+    #[test]
+    fn test_root_hash_matches_ethereum() {
+        // Test vectors from https://github.com/ethereum/tests/blob/develop/TrieTests/trieanyorder.json
+        assert_eq!(
+            MerklePatriciaTrie::new().root_hash(),
+            hash("56e81f171bcc55a6ff8345e692c0f86e5b48e01b996cadc001622fb5e363b421")
+        );
+        assert_eq!(
+            trie_from(&[
+                ("doe", "reindeer"),
+                ("dog", "puppy"),
+                ("dogglesworth", "cat")
+            ])
+            .root_hash(),
+            hash("8aad789dff2f538bca5d8ea56e8abe10f4c7ba3a5dea95fea4cd6e7c3a1168d3")
+        );
+        assert_eq!(
+            trie_from(&PUPPY).root_hash(),
+            hash("5991bb8c6514148a29db676a14ac506cd2cd5775ace63c30a4fe457715e9ac84")
+        );
+    }
+
+    #[test]
+    fn test_insert_get_remove() {
+        let mut trie = trie_from(&PUPPY);
+        assert_eq!(trie.len(), 4);
+        assert_eq!(trie.get(b"dog"), Some(b"puppy".as_slice()));
+        assert_eq!(trie.get(b"do"), Some(b"verb".as_slice()));
+        assert_eq!(trie.get(b"d"), None);
+        assert_eq!(trie.get(b"doges"), None);
+
+        assert_eq!(
+            trie.insert(b"dog", b"hound".to_vec()),
+            Some(b"puppy".to_vec())
+        );
+        assert_eq!(trie.remove(b"doge"), Some(b"coin".to_vec()));
+        assert_eq!(trie.remove(b"doge"), None);
+        assert_eq!(trie.insert(b"do", Vec::new()), Some(b"verb".to_vec()));
+        assert_eq!(trie.len(), 2);
+        assert_eq!(trie.get(b"dog"), Some(b"hound".as_slice()));
+    }
+
+    #[test]
+    fn test_root_hash_is_independent_of_history() {
+        let mut trie = trie_from(&PUPPY);
+        trie.insert(b"dogfish", b"shark".to_vec());
+        trie.insert(b"d", b"letter".to_vec());
+        trie.remove(b"dogfish");
+        trie.remove(b"d");
+        assert_eq!(trie.root_hash(), trie_from(&PUPPY).root_hash());
+
+        for (key, _) in PUPPY {
+            trie.remove(key.as_bytes());
+        }
+        assert!(trie.is_empty());
+        assert_eq!(trie.root_hash(), MerklePatriciaTrie::new().root_hash());
+    }
+
+    #[test]
+    fn test_proofs() {
+        let mut trie = trie_from(&PUPPY);
+        for i in 0..50u32 {
+            trie.insert(&i.to_be_bytes(), format!("value {i}").into_bytes());
+        }
+        let root = trie.root_hash();
+
+        for (key, value) in PUPPY {
+            let proof = trie.prove(key.as_bytes());
+            assert_eq!(
+                verify_proof(&root, key.as_bytes(), &proof),
+                Ok(Some(value.as_bytes().to_vec()))
+            );
+        }
+        let proof = trie.prove(&7u32.to_be_bytes());
+        assert_eq!(
+            verify_proof(&root, &7u32.to_be_bytes(), &proof),
+            Ok(Some(b"value 7".to_vec()))
+        );
+
+        // Exclusion, for keys ending inside a leaf, an extension, or at an empty branch slot.
+        for key in [b"dogs".as_slice(), b"d", b"cat", &100u32.to_be_bytes()] {
+            let proof = trie.prove(key);
+            assert_eq!(verify_proof(&root, key, &proof), Ok(None));
+        }
+    }
+
+    #[test]
+    fn test_invalid_proofs() {
+        let mut trie = trie_from(&PUPPY);
+        for i in 0..50u32 {
+            trie.insert(&i.to_be_bytes(), format!("value {i}").into_bytes());
+        }
+        let root = trie.root_hash();
+        let key = 7u32.to_be_bytes();
+
+        // A proof for another trie doesn't check out against this root.
+        let other = trie_from(&PUPPY);
+        assert!(matches!(
+            verify_proof(&root, &key, &other.prove(&key)),
+            Err(ProofError::MissingNode(_))
+        ));
+
+        // Neither does a proof with a node missing or tampered with.
+        let mut proof = trie.prove(&key);
+        assert!(proof.len() > 1);
+        proof.pop();
+        assert!(verify_proof(&root, &key, &proof).is_err());
+
+        let mut proof = trie.prove(&key);
+        let last = proof.last_mut().unwrap();
+        *last.last_mut().unwrap() ^= 1;
+        assert!(verify_proof(&root, &key, &proof).is_err());
+    }
 }
@@ -0,0 +1,29 @@
+// Builds a small Merkle Patricia trie and checks proofs against its root.
+
+use learning_impl::trie::patricia::{MerklePatriciaTrie, to_hex, verify_proof};
+
+fn main() {
+    let mut trie = MerklePatriciaTrie::new();
+    println!("Empty root: {}", to_hex(&trie.root_hash()));
+
+    for (key, value) in [
+        ("do", "verb"),
+        ("dog", "puppy"),
+        ("doge", "coin"),
+        ("horse", "stallion"),
+    ] {
+        trie.insert(key.as_bytes(), value.as_bytes().to_vec());
+    }
+    let root = trie.root_hash();
+    println!("Root: {}", to_hex(&root));
+
+    for key in ["dog", "dot"] {
+        let proof = trie.prove(key.as_bytes());
+        let value = verify_proof(&root, key.as_bytes(), &proof).unwrap();
+        println!(
+            "{key}: {:?} (proof of {} nodes)",
+            value.map(|v| String::from_utf8(v).unwrap()),
+            proof.len()
+        );
+    }
+}
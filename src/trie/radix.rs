@@ -3,17 +3,330 @@
 // Radix tree is similar to a normal trie but each node can have more than one character.
 // Its also known as a compressed trie.
 
-use std::collections::HashMap;
+use std::borrow::Cow;
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::sync::OnceLock;
+use std::sync::atomic::{AtomicU64, Ordering};
+use unicode_normalization::UnicodeNormalization;
+use unicode_segmentation::{GraphemeCursor, UnicodeSegmentation};
 
+// Labels up to this many bytes are stored inline in the edge, longer ones live in the trie's label arena.
+const INLINE_LABEL_CAPACITY: usize = 14;
+
+/// The label of an edge. Most labels are short and fit in the edge itself, longer ones point into a shared
+/// byte arena instead of being a separate heap allocation each.
+#[derive(Debug, Clone, Copy)]
+enum Label {
+    Inline {
+        len: u8,
+        bytes: [u8; INLINE_LABEL_CAPACITY],
+    },
+    Arena {
+        start: u32,
+        len: u32,
+    },
+}
+
+impl Label {
+    fn len(&self) -> usize {
+        match self {
+            Label::Inline { len, .. } => *len as usize,
+            Label::Arena { len, .. } => *len as usize,
+        }
+    }
+
+    // Splits the label in two at `index`, which must be a char boundary. This never allocates, arena labels
+    // are simply split into two ranges of the same bytes.
+    fn split_at(&self, index: usize) -> (Label, Label) {
+        match *self {
+            Label::Inline { len, bytes } => (
+                Label::inline(&bytes[..index]),
+                Label::inline(&bytes[index..len as usize]),
+            ),
+            Label::Arena { start, len } => (
+                Label::Arena {
+                    start,
+                    len: index as u32,
+                },
+                Label::Arena {
+                    start: start + index as u32,
+                    len: len - index as u32,
+                },
+            ),
+        }
+    }
+
+    fn inline(label: &[u8]) -> Label {
+        let mut bytes = [0; INLINE_LABEL_CAPACITY];
+        bytes[..label.len()].copy_from_slice(label);
+        Label::Inline {
+            len: label.len() as u8,
+            bytes,
+        }
+    }
+}
+
+/// Holds the bytes of every label too long to be stored inline. Bytes are never removed, so labels dropped by
+/// `delete` leave some garbage behind until the trie itself is dropped.
 #[derive(Debug, Clone, Default)]
-struct RadixTrieNode {
-    children: HashMap<String, RadixTrieNode>,
-    is_terminal: bool,
+struct LabelArena {
+    bytes: String,
 }
 
+impl LabelArena {
+    fn alloc(&mut self, label: &str) -> Label {
+        if label.len() <= INLINE_LABEL_CAPACITY {
+            return Label::inline(label.as_bytes());
+        }
+        let start = self.bytes.len() as u32;
+        self.bytes.push_str(label);
+        Label::Arena {
+            start,
+            len: label.len() as u32,
+        }
+    }
+
+    fn get<'a>(&'a self, label: &'a Label) -> &'a str {
+        match label {
+            Label::Inline { len, bytes } => {
+                std::str::from_utf8(&bytes[..*len as usize]).expect("labels are valid UTF-8")
+            }
+            Label::Arena { start, len } => &self.bytes[*start as usize..(*start + *len) as usize],
+        }
+    }
+
+    fn bytes<'a>(&'a self, label: &'a Label) -> &'a [u8] {
+        match label {
+            Label::Inline { len, bytes } => &bytes[..*len as usize],
+            Label::Arena { start, len } => {
+                &self.bytes.as_bytes()[*start as usize..(*start + *len) as usize]
+            }
+        }
+    }
+
+    // Joins two labels into one, reusing the arena bytes when the labels were split from the same range.
+    fn join(&mut self, head: &Label, tail: &Label) -> Label {
+        if let (
+            Label::Arena { start, len },
+            Label::Arena {
+                start: tail_start,
+                len: tail_len,
+            },
+        ) = (head, tail)
+            && start + len == *tail_start
+        {
+            return Label::Arena {
+                start: *start,
+                len: len + tail_len,
+            };
+        }
+        let joined = format!("{}{}", self.get(head), self.get(tail));
+        self.alloc(&joined)
+    }
+}
+
+/// The structural hash of a node, computed on demand and reset whenever the subtree below the node changes.
+/// Zero means it hasn't been computed yet.
 #[derive(Debug, Default)]
-pub struct RadixTrie {
-    root: RadixTrieNode,
+struct CachedHash(AtomicU64);
+
+impl Clone for CachedHash {
+    fn clone(&self) -> Self {
+        Self(AtomicU64::new(self.0.load(Ordering::Relaxed)))
+    }
+}
+
+impl CachedHash {
+    fn get_or_compute(&self, compute: impl FnOnce() -> u64) -> u64 {
+        let hash = self.0.load(Ordering::Relaxed);
+        if hash != 0 {
+            return hash;
+        }
+        let hash = compute().max(1);
+        self.0.store(hash, Ordering::Relaxed);
+        hash
+    }
+
+    fn invalidate(&mut self) {
+        *self.0.get_mut() = 0;
+    }
+}
+
+#[derive(Debug, Clone)]
+struct RadixTrieNode<V> {
+    // Sorted by label.
+    children: Vec<RadixTrieEdge<V>>,
+    // The value of the word ending here, only set on terminal nodes.
+    value: Option<V>,
+    // The word as it was inserted, only kept when it differs from its normalized key.
+    original: Option<Box<str>>,
+    hash: CachedHash,
+    // The largest value stored in the subtree, computed on demand by `top_k` and reset along with the hash.
+    best: OnceLock<Option<V>>,
+}
+
+impl<V> Default for RadixTrieNode<V> {
+    fn default() -> Self {
+        Self {
+            children: Vec::new(),
+            value: None,
+            original: None,
+            hash: CachedHash::default(),
+            best: OnceLock::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct RadixTrieEdge<V> {
+    label: Label,
+    node: RadixTrieNode<V>,
+}
+
+impl<V> RadixTrieNode<V> {
+    fn is_terminal(&self) -> bool {
+        self.value.is_some()
+    }
+
+    // Resets what is cached about the subtree, for when it changes.
+    fn invalidate(&mut self) {
+        self.hash.invalidate();
+        self.best.take();
+    }
+
+    fn best(&self) -> Option<&V>
+    where
+        V: Ord + Clone,
+    {
+        self.best
+            .get_or_init(|| {
+                let children_best = self.children.iter().filter_map(|edge| edge.node.best());
+                children_best.chain(self.value.as_ref()).max().cloned()
+            })
+            .as_ref()
+    }
+
+    // Adds a new child, keeping the children sorted, and returns its index.
+    fn insert_child(&mut self, arena: &LabelArena, label: Label) -> usize {
+        let index = self
+            .children
+            .binary_search_by(|edge| arena.get(&edge.label).cmp(arena.get(&label)))
+            .unwrap_or_else(|index| index);
+        // Most nodes only have a couple of children, so don't let the vector over-allocate.
+        self.children.reserve_exact(1);
+        self.children.insert(
+            index,
+            RadixTrieEdge {
+                label,
+                node: Default::default(),
+            },
+        );
+        index
+    }
+
+    // Finds the child whose label is a prefix of `word`.
+    fn find_child(&self, arena: &LabelArena, split_mode: SplitMode, word: &str) -> Option<usize> {
+        self.children.iter().position(|edge| {
+            let label = arena.bytes(&edge.label);
+            word.as_bytes().starts_with(label) && split_mode.is_boundary(word, label.len())
+        })
+    }
+
+    // Hashes the keys stored below the node. It only depends on the labels and which nodes are terminal, so
+    // subtrees holding the same keys in the same shape hash the same, even across tries.
+    fn structural_hash(&self, arena: &LabelArena) -> u64 {
+        self.hash.get_or_compute(|| {
+            let mut hasher = DefaultHasher::new();
+            self.is_terminal().hash(&mut hasher);
+            for edge in &self.children {
+                arena.get(&edge.label).hash(&mut hasher);
+                edge.node.structural_hash(arena).hash(&mut hasher);
+            }
+            hasher.finish()
+        })
+    }
+}
+
+/// How words are turned into keys before they are inserted or looked up.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum KeyNormalization {
+    /// Words are used as they are.
+    #[default]
+    None,
+    /// Canonical composition, so "e\u{301}" and "é" are the same key.
+    Nfc,
+    /// Compatibility composition, which also folds forms like "ﬁ" into "fi".
+    Nfkc,
+    /// Compatibility composition followed by lowercasing, so "Hello" and "hello" are the same key.
+    CaseFold,
+}
+
+impl KeyNormalization {
+    fn normalize<'a>(&self, word: &'a str) -> Cow<'a, str> {
+        match self {
+            KeyNormalization::None => Cow::Borrowed(word),
+            KeyNormalization::Nfc => Cow::Owned(word.nfc().collect()),
+            KeyNormalization::Nfkc => Cow::Owned(word.nfkc().collect()),
+            KeyNormalization::CaseFold => {
+                Cow::Owned(word.nfkc().collect::<String>().to_lowercase())
+            }
+        }
+    }
+}
+
+/// Where edges are allowed to be split when two keys diverge.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SplitMode {
+    /// Edges can be split between any two `char`s.
+    #[default]
+    Char,
+    /// Edges are only split between extended grapheme clusters, so a base character always stays with its
+    /// combining marks and emoji sequences are never cut in half.
+    Grapheme,
+}
+
+impl SplitMode {
+    fn common_prefix<'a>(&self, word_a: &'a str, word_b: &'a str) -> &'a str {
+        match self {
+            SplitMode::Char => get_common_prefix(word_a, word_b),
+            SplitMode::Grapheme => get_common_grapheme_prefix(word_a, word_b),
+        }
+    }
+
+    // Whether `word` can be split at `index`, which must be a char boundary.
+    fn is_boundary(&self, word: &str, index: usize) -> bool {
+        match self {
+            SplitMode::Char => true,
+            SplitMode::Grapheme => GraphemeCursor::new(index, word.len(), true)
+                .is_boundary(word, 0)
+                .unwrap_or(false),
+        }
+    }
+}
+
+/// A radix trie of words, each carrying a value. With the default `()` value it's simply a set of words.
+#[derive(Debug, Clone)]
+pub struct RadixTrie<V = ()> {
+    root: RadixTrieNode<V>,
+    labels: LabelArena,
+    normalization: KeyNormalization,
+    split_mode: SplitMode,
+    len: usize,
+}
+
+impl<V> Default for RadixTrie<V> {
+    fn default() -> Self {
+        Self {
+            root: RadixTrieNode::default(),
+            labels: LabelArena::default(),
+            normalization: KeyNormalization::default(),
+            split_mode: SplitMode::default(),
+            len: 0,
+        }
+    }
 }
 
 impl RadixTrie {
@@ -21,113 +334,722 @@ impl RadixTrie {
         Default::default()
     }
 
-    pub fn insert(&mut self, input_word: &str) {
-        if input_word.is_empty() {
-            return;
-        }
+    /// Inserts a word, returning whether it wasn't in the trie yet. The empty word is stored on the root.
+    pub fn insert(&mut self, word: &str) -> bool {
+        self.insert_value(word, ()).is_none()
+    }
+}
 
-        let mut current: &mut RadixTrieNode = &mut self.root;
-        let mut current_word = input_word;
+impl<V> RadixTrie<V> {
+    /// Normalizes every word before inserting or looking it up.
+    pub fn with_normalization(mut self, normalization: KeyNormalization) -> Self {
+        self.normalization = normalization;
+        self
+    }
+
+    /// Sets where edges can be split. This should be chosen before inserting any word.
+    pub fn with_split_mode(mut self, split_mode: SplitMode) -> Self {
+        self.split_mode = split_mode;
+        self
+    }
+
+    /// Inserts a word with its value, returning the previous value if the word was already in the trie. The empty
+    /// word is stored on the root.
+    pub fn insert_value(&mut self, input_word: &str, value: V) -> Option<V> {
+        let key = self.normalization.normalize(input_word);
+        let split_mode = self.split_mode;
+        let labels = &mut self.labels;
+        let mut current: &mut RadixTrieNode<V> = &mut self.root;
+        let mut current_word = key.as_ref();
 
         while !current_word.is_empty() {
+            current.invalidate();
+
             // Find common prefix and potential next node.
-            let next_keys = current.children.keys().find_map(|child_key| {
-                let common_prefix = get_common_prefix(current_word, child_key);
-                if !common_prefix.is_empty() {
-                    return Some((child_key.to_owned(), common_prefix.to_owned()));
-                }
-                None
-            });
+            let next_edge = current
+                .children
+                .iter()
+                .enumerate()
+                .find_map(|(index, edge)| {
+                    let common_prefix =
+                        split_mode.common_prefix(current_word, labels.get(&edge.label));
+                    if !common_prefix.is_empty() {
+                        return Some((index, common_prefix.len()));
+                    }
+                    None
+                });
 
-            // With the common prefix and the next node known, we now go through the cases.]
-            // 1. if no common prefix, insert the node as it is /
-            // 2. if common prefix == input word length, return immediately (node already exists)
-            // 3. if common prefix length == value length of the nodes, continue down (continue searching)
-            // 4. if common prefix not equals, then split the node into its common prefix and its postfix(reorg).
-            let (next_possible_node, next_key) = match next_keys {
-                None => {
-                    // Case 1: no common prefix, insert new word whole from root.
-                    current.children.insert(
-                        current_word.to_string(),
-                        RadixTrieNode {
-                            is_terminal: true,
-                            children: HashMap::new(),
-                        },
-                    );
-                    break;
-                }
-                Some((next_possible_node, next_key)) => (next_possible_node, next_key),
+            // With the common prefix and the next node known, we now go through the cases.
+            // 1. if no common prefix, insert the rest of the word as a new node.
+            // 2. if common prefix == label of the edge, continue down (the loop ends there if the word is used up).
+            // 3. if common prefix is shorter than the label, split the edge into its common prefix and its postfix.
+            let Some((index, common_len)) = next_edge else {
+                // Case 1: no common prefix, insert the rest of the word as a new leaf.
+                let label = labels.alloc(current_word);
+                let index = current.insert_child(labels, label);
+                current = &mut current.children[index].node;
+                break;
             };
 
-            if next_possible_node == current_word {
-                // Case 2: common prefix exists as a node, and its the same as the input, thus the node already exists, and we don't insert.
-                current_word = ""
-            } else if next_key == next_possible_node {
-                // Case 3: there's already a node of the prefix, so we continue searching
-                let next_node = current.children.get_mut(&next_possible_node).unwrap();
-                current_word = &current_word[next_key.len()..];
-                current = next_node;
-            } else {
-                // Case 4: There's a common prefix, and an existing node, we split the node and reorg the tree.
-                let current_next_node = current.children.remove(&next_possible_node).unwrap();
-                let current_next_node_new_key = next_possible_node
-                    .clone()
-                    .strip_prefix(&next_key)
-                    .unwrap()
-                    .to_owned();
-
-                // Special case where the prefix is also the same as the new word inserted.
-                // in this case terminate early.
-                let is_new_node_terminal = next_key == current_word;
-
-                let mut new_next_node = RadixTrieNode {
-                    children: HashMap::new(),
-                    is_terminal: is_new_node_terminal,
-                };
-                new_next_node
-                    .children
-                    .insert(current_next_node_new_key, current_next_node.to_owned());
+            let edge = &mut current.children[index];
+            if common_len < edge.label.len() {
+                // Case 3: split the edge, the new intermediate node is then handled like case 2.
+                let (head, tail) = edge.label.split_at(common_len);
+                let child = std::mem::take(&mut edge.node);
+                edge.label = head;
+                edge.node.children.reserve_exact(1);
+                edge.node.children.push(RadixTrieEdge {
+                    label: tail,
+                    node: child,
+                });
+            }
 
-                current
-                    .children
-                    .insert(next_key.clone(), new_next_node.to_owned());
+            // Case 2: follow the edge.
+            current_word = &current_word[common_len..];
+            current = &mut current.children[index].node;
+        }
 
-                current_word = &current_word[next_key.len()..];
-                current = current.children.get_mut(&next_key).unwrap();
-            }
+        // `current` is now the node of the word. Remember how it was spelled if normalizing changed it.
+        current.invalidate();
+        if let Some(previous) = current.value.replace(value) {
+            return Some(previous);
+        }
+        if key != input_word {
+            current.original = Some(input_word.into());
         }
+        self.len += 1;
+        None
     }
 
     pub fn search(&self, word: &str) -> bool {
-        if word.is_empty() {
-            return false;
+        self.get(word).is_some()
+    }
+
+    /// Returns the value of a word, if it's in the trie.
+    pub fn get(&self, word: &str) -> Option<&V> {
+        let key = self.normalization.normalize(word);
+        let mut current_node = &self.root;
+        let mut word_part = key.as_ref();
+
+        while !word_part.is_empty() {
+            let next_edge = current_node.find_child(&self.labels, self.split_mode, word_part);
+
+            if let Some(index) = next_edge {
+                let edge = &current_node.children[index];
+                word_part = &word_part[edge.label.len()..];
+                current_node = &edge.node;
+            } else {
+                return None;
+            }
         }
+        current_node.value.as_ref()
+    }
+
+    /// Deletes a word, returning whether it was in the trie.
+    pub fn delete(&mut self, word: &str) -> bool {
+        self.remove(word).is_some()
+    }
+
+    /// Removes a word, returning its value if it was in the trie.
+    pub fn remove(&mut self, word: &str) -> Option<V> {
+        let key = self.normalization.normalize(word);
+        let (value, _) =
+            recursively_delete_node(&mut self.root, &mut self.labels, &key, self.split_mode)?;
+        self.len -= 1;
+        Some(value)
+    }
+
+    /// Returns the number of words in the trie.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns every word stored in the trie, as it was originally inserted, in the lexicographic order of their keys.
+    pub fn words(&self) -> Vec<String> {
+        let mut words = Vec::new();
+        visit_words(
+            &self.labels,
+            &self.root,
+            &mut String::new(),
+            &mut |key, node| {
+                words.push(node.original.as_deref().unwrap_or(key).to_string());
+            },
+        );
+        words
+    }
+
+    /// Returns every key stored in the trie, as normalized, in trie order. That's the byte order of the keys, except
+    /// with `SplitMode::Grapheme` where sibling edges can share their first char: "e日" below "e" comes before
+    /// "e\u{301}".
+    pub fn keys(&self) -> impl Iterator<Item = String> {
+        let mut keys = Vec::new();
+        visit_words(
+            &self.labels,
+            &self.root,
+            &mut String::new(),
+            &mut |key, _| keys.push(key.to_string()),
+        );
+        keys.into_iter()
+    }
+
+    /// Returns the words whose key starts with `prefix`, as they were originally inserted.
+    pub fn words_with_prefix(&self, prefix: &str) -> Vec<String> {
+        let mut words = Vec::new();
+        for (mut path, node) in self.prefix_nodes(prefix) {
+            visit_words(&self.labels, node, &mut path, &mut |key, node| {
+                words.push(original(node, key))
+            });
+        }
+        words
+    }
+
+    /// Returns the values of the words whose key starts with `prefix`, in trie order.
+    pub fn values_with_prefix(&self, prefix: &str) -> Vec<&V> {
+        let mut values = Vec::new();
+        for (mut path, node) in self.prefix_nodes(prefix) {
+            visit_words(&self.labels, node, &mut path, &mut |_, node| {
+                values.extend(node.value.as_ref())
+            });
+        }
+        values
+    }
+
+    /// Returns up to `k` words whose key starts with `prefix`, with the largest values first and as they were
+    /// originally inserted. Words with the same value come in the order of their keys.
+    ///
+    /// Every node caches the largest value of its subtree, so the most promising branches are walked first and the
+    /// search stops once it has `k` words, instead of going through every word starting with `prefix`.
+    pub fn top_k(&self, prefix: &str, k: usize) -> Vec<(String, &V)>
+    where
+        V: Ord + Clone,
+    {
+        let mut results = Vec::new();
+        if k == 0 {
+            return results;
+        }
+
+        let mut queue = BinaryHeap::new();
+        for (path, node) in self.prefix_nodes(prefix) {
+            if let Some(best) = node.best() {
+                queue.push(Candidate {
+                    value: best,
+                    is_word: false,
+                    key: Reverse(path),
+                    node,
+                });
+            }
+        }
+
+        while let Some(candidate) = queue.pop() {
+            if candidate.is_word {
+                results.push((original(candidate.node, &candidate.key.0), candidate.value));
+                if results.len() == k {
+                    break;
+                }
+                continue;
+            }
+
+            // Expand the node: its own word competes with the best words of each child subtree.
+            let node = candidate.node;
+            if let Some(value) = &node.value {
+                queue.push(Candidate {
+                    value,
+                    is_word: true,
+                    key: candidate.key.clone(),
+                    node,
+                });
+            }
+            for edge in &node.children {
+                if let Some(best) = edge.node.best() {
+                    queue.push(Candidate {
+                        value: best,
+                        is_word: false,
+                        key: Reverse(format!(
+                            "{}{}",
+                            candidate.key.0,
+                            self.labels.get(&edge.label)
+                        )),
+                        node: &edge.node,
+                    });
+                }
+            }
+        }
+        results
+    }
+
+    // Finds the nodes under which the words whose key starts with `prefix` live, with the key leading to each. The
+    // prefix can end halfway through an edge, the whole edge is followed then. With grapheme splits, it can even
+    // end in several sibling edges, like "e" ending in both "e" and "e\u{301}".
+    fn prefix_nodes(&self, prefix: &str) -> Vec<(String, &RadixTrieNode<V>)> {
+        let key = self.normalization.normalize(prefix);
         let mut current_node = &self.root;
-        let mut word_part = word;
+        let mut path = String::new();
+        let mut word_part = key.as_ref();
 
         while !word_part.is_empty() {
-            let next_node = current_node
+            let ending: Vec<_> = current_node
                 .children
                 .iter()
-                .find(|(key, _)| word_part.starts_with(*key));
+                .filter(|edge| self.labels.get(&edge.label).starts_with(word_part))
+                .map(|edge| {
+                    (
+                        format!("{path}{}", self.labels.get(&edge.label)),
+                        &edge.node,
+                    )
+                })
+                .collect();
+            if !ending.is_empty() {
+                return ending;
+            }
 
-            if let Some((key, node)) = next_node {
-                word_part = &word_part[key.len()..];
-                current_node = node;
-            } else {
-                return false;
+            let Some(index) = current_node.find_child(&self.labels, self.split_mode, word_part)
+            else {
+                return Vec::new();
+            };
+            let edge = &current_node.children[index];
+            path.push_str(self.labels.get(&edge.label));
+            word_part = &word_part[edge.label.len()..];
+            current_node = &edge.node;
+        }
+        vec![(path, current_node)]
+    }
+
+    /// Returns the words that would have to be added to or removed from this trie to get `other`.
+    ///
+    /// Subtrees are compared by their structural hash first, and skipped when the hashes match, so tries that
+    /// mostly agree are compared without walking all of their words. Words are compared by their keys, so both
+    /// tries should use the same `KeyNormalization`. Values aren't compared.
+    pub fn diff(&self, other: &RadixTrie<V>) -> impl Iterator<Item = Change> {
+        let mut differ = Differ {
+            old_labels: &self.labels,
+            new_labels: &other.labels,
+            prefix: String::new(),
+            changes: Vec::new(),
+        };
+        differ.diff(Position::at(&self.root), Position::at(&other.root));
+        differ.changes.into_iter()
+    }
+
+    /// Returns a cursor positioned at the root, to walk the trie one character or byte at a time.
+    pub fn cursor(&self) -> RadixCursor<'_, V> {
+        RadixCursor {
+            trie: self,
+            node: &self.root,
+            edge: None,
+            offset: 0,
+            pending: [0; 4],
+            pending_len: 0,
+            is_dead: false,
+        }
+    }
+}
+
+// An entry of the best-first search of `top_k`: either a subtree still to be expanded, with the largest value in it,
+// or the word of `node`, ready to be returned.
+struct Candidate<'a, V> {
+    value: &'a V,
+    is_word: bool,
+    key: Reverse<String>,
+    node: &'a RadixTrieNode<V>,
+}
+
+impl<V: Ord> Ord for Candidate<'_, V> {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        // Expand a subtree before returning a word with the same value, since the subtree can still hold a word
+        // with that value and a smaller key.
+        (self.value, !self.is_word, &self.key).cmp(&(other.value, !other.is_word, &other.key))
+    }
+}
+
+impl<V: Ord> PartialOrd for Candidate<'_, V> {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<V: Ord> PartialEq for Candidate<'_, V> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == std::cmp::Ordering::Equal
+    }
+}
+
+impl<V: Ord> Eq for Candidate<'_, V> {}
+
+/// Walks a `RadixTrie` incrementally, as if the input fed so far was a prefix being searched.
+///
+/// Input is matched against the keys as they are stored, so with a `KeyNormalization` other than `None` the input
+/// has to be normalized already.
+#[derive(Debug, Clone)]
+pub struct RadixCursor<'a, V = ()> {
+    trie: &'a RadixTrie<V>,
+    // The node whose children we're walking.
+    node: &'a RadixTrieNode<V>,
+    // The child edge being walked and how many of its bytes were consumed. The offset can be the full label length,
+    // we only move on to the child node once the next character tells us the walk doesn't continue on a sibling.
+    edge: Option<usize>,
+    offset: usize,
+    // Bytes of a character that was only partially fed through `feed_byte`.
+    pending: [u8; 4],
+    pending_len: usize,
+    is_dead: bool,
+}
+
+impl<'a, V> RadixCursor<'a, V> {
+    /// Feeds the next character, returning whether the input fed so far is still a prefix of some key.
+    pub fn feed_char(&mut self, c: char) -> bool {
+        if self.is_dead || self.pending_len > 0 {
+            self.is_dead = true;
+            return false;
+        }
+
+        let mut buffer = [0; 4];
+        let c = c.encode_utf8(&mut buffer);
+
+        // If the current edge is fully consumed, the walk can continue on one of the child node's edges.
+        if let Some(edge) = self.current_edge()
+            && self.offset == edge.label.len()
+        {
+            let child = edge.node.children.iter().position(|child| {
+                self.trie
+                    .labels
+                    .bytes(&child.label)
+                    .starts_with(c.as_bytes())
+            });
+            if let Some(index) = child {
+                self.node = &edge.node;
+                self.edge = Some(index);
+                self.offset = c.len();
+                return true;
+            }
+        }
+
+        // Otherwise it continues on an edge of the current node, which is either the current edge or, when splitting
+        // on graphemes, a sibling sharing the same start.
+        let consumed = self.consumed();
+        let next = self.node.children.iter().position(|edge| {
+            let label = self.trie.labels.get(&edge.label);
+            label.starts_with(consumed) && label[consumed.len()..].starts_with(&*c)
+        });
+        match next {
+            Some(index) => {
+                self.edge = Some(index);
+                self.offset += c.len();
+                true
+            }
+            None => {
+                self.is_dead = true;
+                false
+            }
+        }
+    }
+
+    /// Feeds the next byte of UTF-8 input, returning whether the input fed so far is still a prefix of some key.
+    pub fn feed_byte(&mut self, byte: u8) -> bool {
+        if self.is_dead {
+            return false;
+        }
+
+        self.pending[self.pending_len] = byte;
+        self.pending_len += 1;
+        match std::str::from_utf8(&self.pending[..self.pending_len]) {
+            Ok(c) => {
+                let c = c.chars().next().unwrap();
+                self.pending_len = 0;
+                self.feed_char(c)
+            }
+            // The character isn't complete yet, make sure it can still become one of the next characters.
+            Err(error) if error.error_len().is_none() => {
+                let can_continue = self.can_continue();
+                self.is_dead = !can_continue;
+                can_continue
+            }
+            Err(_) => {
+                self.is_dead = true;
+                false
+            }
+        }
+    }
+
+    /// Whether the input fed so far is a key of the trie.
+    pub fn is_terminal(&self) -> bool {
+        self.value().is_some()
+    }
+
+    /// The value of the input fed so far, if it's a key of the trie.
+    pub fn value(&self) -> Option<&'a V> {
+        if self.is_dead || self.pending_len > 0 {
+            return None;
+        }
+        match self.current_edge() {
+            Some(edge) if self.offset == edge.label.len() => edge.node.value.as_ref(),
+            Some(_) => None,
+            None => self.node.value.as_ref(),
+        }
+    }
+
+    /// Whether feeding more input can still lead to a key.
+    pub fn can_continue(&self) -> bool {
+        self.next_chars().next().is_some()
+    }
+
+    /// The characters that can be fed next while still being a prefix of some key, in order.
+    pub fn next_chars(&self) -> impl Iterator<Item = char> + '_ {
+        let labels = &self.trie.labels;
+        let consumed = if self.is_dead {
+            None
+        } else {
+            Some(self.consumed())
+        };
+
+        // Characters continuing an edge of the current node.
+        let on_edges = consumed.into_iter().flat_map(move |consumed| {
+            self.node.children.iter().filter_map(move |edge| {
+                let label = labels.get(&edge.label);
+                if label.len() > consumed.len() && label.starts_with(consumed) {
+                    label[consumed.len()..].chars().next()
+                } else {
+                    None
+                }
+            })
+        });
+
+        // Characters starting an edge of the child node, if the current edge is fully consumed.
+        let on_child_edges = self
+            .current_edge()
+            .filter(|edge| !self.is_dead && self.offset == edge.label.len())
+            .into_iter()
+            .flat_map(move |edge| {
+                edge.node
+                    .children
+                    .iter()
+                    .filter_map(move |child| labels.get(&child.label).chars().next())
+            });
+
+        let pending = &self.pending[..self.pending_len];
+        let mut previous = None;
+        on_edges
+            .chain(on_child_edges)
+            .filter(move |c| {
+                // Edges are sorted, so repeated characters are next to each other.
+                let is_repeated = previous == Some(*c);
+                previous = Some(*c);
+                !is_repeated
+            })
+            .filter(move |c| c.encode_utf8(&mut [0; 4]).as_bytes().starts_with(pending))
+    }
+
+    fn current_edge(&self) -> Option<&'a RadixTrieEdge<V>> {
+        self.edge.map(|index| &self.node.children[index])
+    }
+
+    // The part of the current edge's label that was already fed.
+    fn consumed(&self) -> &'a str {
+        match self.current_edge() {
+            Some(edge) => &self.trie.labels.get(&edge.label)[..self.offset],
+            None => "",
+        }
+    }
+}
+
+/// A difference between two tries, as returned by `RadixTrie::diff`. Words are spelled as they were inserted.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Change {
+    /// The word is only in the other trie.
+    Added(String),
+    /// The word is only in this trie.
+    Removed(String),
+}
+
+// A position along the paths of a trie: either on a node, or `pending` bytes before reaching it on an edge. The
+// same key can end on a node in one trie and halfway through an edge in another, so the tries are compared
+// position by position rather than node by node.
+struct Position<'a, V> {
+    node: &'a RadixTrieNode<V>,
+    pending: &'a str,
+}
+
+// Derived impls would require `V: Copy`.
+impl<V> Clone for Position<'_, V> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<V> Copy for Position<'_, V> {}
+
+impl<'a, V> Position<'a, V> {
+    fn at(node: &'a RadixTrieNode<V>) -> Self {
+        Self { node, pending: "" }
+    }
+
+    fn is_terminal(&self) -> bool {
+        self.pending.is_empty() && self.node.is_terminal()
+    }
+
+    // The labels leaving the position, and the nodes they lead to, sorted by label.
+    fn edges(&self, labels: &'a LabelArena) -> Vec<(&'a str, &'a RadixTrieNode<V>)> {
+        if !self.pending.is_empty() {
+            return vec![(self.pending, self.node)];
+        }
+        self.node
+            .children
+            .iter()
+            .map(|edge| (labels.get(&edge.label), &edge.node))
+            .collect()
+    }
+}
+
+struct Differ<'a> {
+    old_labels: &'a LabelArena,
+    new_labels: &'a LabelArena,
+    // The key leading to the positions being compared.
+    prefix: String,
+    changes: Vec<Change>,
+}
+
+impl<'a> Differ<'a> {
+    fn diff<V>(&mut self, old: Position<'a, V>, new: Position<'a, V>) {
+        if old.pending == new.pending
+            && old.node.structural_hash(self.old_labels)
+                == new.node.structural_hash(self.new_labels)
+        {
+            return;
+        }
+
+        match (old.is_terminal(), new.is_terminal()) {
+            (true, false) => self
+                .changes
+                .push(Change::Removed(original(old.node, &self.prefix))),
+            (false, true) => self
+                .changes
+                .push(Change::Added(original(new.node, &self.prefix))),
+            _ => {}
+        }
+
+        // Edges leaving a node start with different characters, except for grapheme split tries where siblings
+        // can share their first char, so pair up the edges of both sides by their first char.
+        let old_edges = old.edges(self.old_labels);
+        let new_edges = new.edges(self.new_labels);
+        let (mut old_index, mut new_index) = (0, 0);
+        while old_index < old_edges.len() || new_index < new_edges.len() {
+            let first_char = |edges: &[(&str, _)], index: usize| {
+                edges.get(index).and_then(|(label, _)| label.chars().next())
+            };
+            let c = match (
+                first_char(&old_edges, old_index),
+                first_char(&new_edges, new_index),
+            ) {
+                (Some(a), Some(b)) => a.min(b),
+                (a, b) => a.or(b).unwrap(),
+            };
+            let old_group = take_group(&old_edges, &mut old_index, c);
+            let new_group = take_group(&new_edges, &mut new_index, c);
+
+            match (old_group, new_group) {
+                ([(old_label, old_node)], [(new_label, new_node)]) => {
+                    let common = get_common_prefix(old_label, new_label);
+                    self.prefix.push_str(common);
+                    self.diff(
+                        Position {
+                            node: old_node,
+                            pending: &old_label[common.len()..],
+                        },
+                        Position {
+                            node: new_node,
+                            pending: &new_label[common.len()..],
+                        },
+                    );
+                    self.prefix.truncate(self.prefix.len() - common.len());
+                }
+                (old_group, new_group) => self.diff_words(old_group, new_group),
             }
         }
-        current_node.is_terminal
     }
 
-    pub fn delete(&mut self, word: &str) {
-        if !word.is_empty() {
-            recursively_delete_node(&mut self.root, word);
+    // Compares the edges by listing all their words, for when they can't be paired one to one.
+    fn diff_words<V>(
+        &mut self,
+        old_group: &[(&str, &RadixTrieNode<V>)],
+        new_group: &[(&str, &RadixTrieNode<V>)],
+    ) {
+        let collect = |labels, group: &[(&str, &RadixTrieNode<V>)], prefix: &str| {
+            let mut words = Vec::new();
+            for (label, node) in group {
+                let mut prefix = format!("{prefix}{label}");
+                visit_words(labels, node, &mut prefix, &mut |key, node| {
+                    words.push((key.to_string(), original(node, key)));
+                });
+            }
+            words.sort();
+            words
+        };
+        let old_words = collect(self.old_labels, old_group, &self.prefix);
+        let new_words = collect(self.new_labels, new_group, &self.prefix);
+
+        let (mut old_words, mut new_words) = (
+            old_words.into_iter().peekable(),
+            new_words.into_iter().peekable(),
+        );
+        loop {
+            let change = match (old_words.peek(), new_words.peek()) {
+                (None, None) => break,
+                (Some(old), Some(new)) if old.0 == new.0 => {
+                    old_words.next();
+                    new_words.next();
+                    continue;
+                }
+                (Some(old), Some(new)) if old.0 < new.0 => {
+                    Change::Removed(old_words.next().unwrap().1)
+                }
+                (Some(_), None) => Change::Removed(old_words.next().unwrap().1),
+                _ => Change::Added(new_words.next().unwrap().1),
+            };
+            self.changes.push(change);
         }
     }
 }
 
+// Takes the edges starting with `c` from the sorted edges, starting at `index`.
+fn take_group<'e, 'a, V>(
+    edges: &'e [(&'a str, &'a RadixTrieNode<V>)],
+    index: &mut usize,
+    c: char,
+) -> &'e [(&'a str, &'a RadixTrieNode<V>)] {
+    let start = *index;
+    while *index < edges.len() && edges[*index].0.starts_with(c) {
+        *index += 1;
+    }
+    &edges[start..*index]
+}
+
+fn original<V>(node: &RadixTrieNode<V>, key: &str) -> String {
+    node.original.as_deref().unwrap_or(key).to_string()
+}
+
+// Calls `visit` with the key and node of every word below `node`, in the lexicographic order of their keys.
+fn visit_words<'a, V>(
+    labels: &LabelArena,
+    node: &'a RadixTrieNode<V>,
+    prefix: &mut String,
+    visit: &mut impl FnMut(&str, &'a RadixTrieNode<V>),
+) {
+    if node.is_terminal() {
+        visit(prefix, node);
+    }
+
+    // Children are kept sorted, so the words come out in order.
+    for edge in &node.children {
+        let label = labels.get(&edge.label);
+        prefix.push_str(label);
+        visit_words(labels, &edge.node, prefix, visit);
+        prefix.truncate(prefix.len() - label.len());
+    }
+}
+
 fn get_common_prefix<'a>(word_a: &'a str, word_b: &'a str) -> &'a str {
     let mut end = 0;
 
@@ -145,71 +1067,78 @@ fn get_common_prefix<'a>(word_a: &'a str, word_b: &'a str) -> &'a str {
     &word_a[..end]
 }
 
-fn recursively_delete_node(node: &mut RadixTrieNode, word: &str) -> bool {
-    if word.is_empty() && node.is_terminal {
-        node.is_terminal = false;
-        if !node.children.is_empty() {
-            return false;
+fn get_common_grapheme_prefix<'a>(word_a: &'a str, word_b: &'a str) -> &'a str {
+    let mut end = 0;
+
+    for (a, b) in word_a.graphemes(true).zip(word_b.graphemes(true)) {
+        if a == b {
+            end += a.len();
+        } else {
+            break;
         }
-        return true;
     }
+    &word_a[..end]
+}
 
-    // Find the child key that is a prefix of the word to follow the path.
-    let next_key = node
-        .children
-        .keys()
-        .find(|&child_key| word.starts_with(child_key))
-        .cloned();
-
-    // if we cannot find the next key, it means the word doesn't exist in the tree.
-    if let Some(next_key) = next_key {
-        let next_word = &word[next_key.len()..];
-
-        let should_delete_node = {
-            let next_node = node.children.get_mut(&next_key).unwrap();
-            recursively_delete_node(next_node, next_word)
-        };
+// Returns `None` if the word isn't in the trie, otherwise its value and whether `node` should now be removed from
+// its parent.
+fn recursively_delete_node<V>(
+    node: &mut RadixTrieNode<V>,
+    labels: &mut LabelArena,
+    word: &str,
+    split_mode: SplitMode,
+) -> Option<(V, bool)> {
+    node.invalidate();
+    if word.is_empty() {
+        let value = node.value.take()?;
+        node.original = None;
+        return Some((value, node.children.is_empty()));
+    }
 
-        if should_delete_node {
-            node.children.remove(&next_key);
+    // Find the child whose label is a prefix of the word to follow the path.
+    // if we cannot find one, it means the word doesn't exist in the tree.
+    let index = node.find_child(labels, split_mode, word)?;
+    let next_word = &word[node.children[index].label.len()..];
 
-            // If there's a non terminal leaf, it should be deleted too.
-            if node.children.is_empty() && !node.is_terminal {
-                return true;
-            }
-        } else {
-            let mut child_node = node.children.remove(&next_key).unwrap();
-
-            // Try to compress the child node.
-            let actual_next_key = if !child_node.is_terminal && child_node.children.len() == 1 {
-                let children_clone = child_node.children.clone();
-                // This definitely exist, since there's only a single child.
-                let child = children_clone.iter().next().unwrap();
-                // This should always work too, given all child should have value.
-                child_node.children = child.1.children.clone();
-                child_node.is_terminal = child.1.is_terminal;
-
-                format!("{}{}", next_key, child.0)
-            } else {
-                next_key.clone()
-            };
+    let (value, should_delete_node) = recursively_delete_node(
+        &mut node.children[index].node,
+        labels,
+        next_word,
+        split_mode,
+    )?;
 
-            if actual_next_key != next_key {
-                node.children.remove(&next_key);
-            }
+    if should_delete_node {
+        node.children.remove(index);
 
-            node.children.insert(actual_next_key, child_node);
+        // If there's a non terminal leaf, it should be deleted too.
+        if node.children.is_empty() && !node.is_terminal() {
+            return Some((value, true));
+        }
+    } else {
+        // Try to compress the child node with its only child.
+        let edge = &mut node.children[index];
+        if !edge.node.is_terminal() && edge.node.children.len() == 1 {
+            // This definitely exist, since there's only a single child.
+            let child = edge.node.children.pop().unwrap();
+            edge.label = labels.join(&edge.label, &child.label);
+            edge.node = child.node;
         }
     }
 
-    false
+    Some((value, false))
 }
 
-fn visualize_trie(node: &RadixTrieNode, label: &str, prefix: &str, is_last: bool) {
+fn visualize_trie<V>(
+    labels: &LabelArena,
+    node: &RadixTrieNode<V>,
+    label: &str,
+    prefix: &str,
+    is_last: bool,
+) {
     // Print the current node
     let marker = if is_last { "└── " } else { "├── " };
     let value = if label.is_empty() { "ROOT" } else { label };
-    let terminal = if node.is_terminal { " (T)" } else { "" };
+    let terminal = if node.is_terminal() { " (T)" } else { "" };
     println!("{prefix}{marker}{value}{terminal}");
 
     // Calculate the new prefix for children
@@ -219,14 +1148,16 @@ fn visualize_trie(node: &RadixTrieNode, label: &str, prefix: &str, is_last: bool
         format!("{prefix}│   ")
     };
 
-    // Sort children for consistent visualization
-    let mut children: Vec<_> = node.children.iter().collect();
-    children.sort_by_key(|(c, _)| *c);
-
-    // Print children
-    for (i, (child_key, child)) in children.iter().enumerate() {
-        let is_last_child = i == children.len() - 1;
-        visualize_trie(child, child_key, &new_prefix, is_last_child);
+    // Print children, which are already sorted for consistent visualization
+    for (i, edge) in node.children.iter().enumerate() {
+        let is_last_child = i == node.children.len() - 1;
+        visualize_trie(
+            labels,
+            &edge.node,
+            labels.get(&edge.label),
+            &new_prefix,
+            is_last_child,
+        );
     }
 }
 
@@ -240,7 +1171,8 @@ fn main() {
     trie.insert("win");
 
     println!("Trie Structure:");
-    visualize_trie(&trie.root, "", "", true);
+    visualize_trie(&trie.labels, &trie.root, "", "", true);
+    println!("Words: {:?}", trie.words());
 
     println!("{:?}", trie.search("hello"));
     println!("{:?}", trie.search("hell"));
@@ -253,11 +1185,11 @@ fn main() {
     println!("{:?}", trie.search("hell"));
 
     println!("Trie Structure after deletion:");
-    visualize_trie(&trie.root, "", "", true);
+    visualize_trie(&trie.labels, &trie.root, "", "", true);
 
     trie.delete("hello");
     println!("Trie Structure after deletion:");
-    visualize_trie(&trie.root, "", "", true);
+    visualize_trie(&trie.labels, &trie.root, "", "", true);
 }
 
 #[cfg(test)]
@@ -288,6 +1220,17 @@ mod tests {
         assert_eq!(result, "abcdefg");
     }
 
+    #[test]
+    fn test_get_common_grapheme_prefix() {
+        // "e" followed by a combining acute accent is a single grapheme.
+        assert_eq!(get_common_grapheme_prefix("cafe\u{301}", "cafe"), "caf");
+        assert_eq!(get_common_prefix("cafe\u{301}", "cafe"), "cafe");
+
+        // Woman technologist and woman scientist share the woman and the zero width joiner.
+        assert_eq!(get_common_grapheme_prefix("a👩‍💻", "a👩‍🔬"), "a");
+        assert_eq!(get_common_prefix("a👩‍💻", "a👩‍🔬"), "a👩\u{200d}");
+    }
+
     #[test]
     fn test_delete_and_compress_logic() {
         let mut trie = RadixTrie::new();
@@ -314,4 +1257,505 @@ mod tests {
         trie.delete("he");
         assert!(trie.search("hello"));
     }
+
+    #[test]
+    fn test_insert_existing_intermediate_node() {
+        let mut trie = RadixTrie::new();
+        trie.insert("testing");
+        trie.insert("tester");
+        trie.insert("test");
+
+        assert!(trie.search("test"));
+        assert!(!trie.search("tes"));
+        assert_eq!(trie.words(), vec!["test", "tester", "testing"]);
+    }
+
+    #[test]
+    fn test_nfc_normalization() {
+        let mut trie = RadixTrie::new().with_normalization(KeyNormalization::Nfc);
+        trie.insert("Cafe\u{301}");
+
+        assert!(trie.search("Café"));
+        assert!(trie.search("Cafe\u{301}"));
+        assert!(!trie.search("café"));
+
+        // Enumeration returns the word as it was first inserted.
+        trie.insert("Café");
+        assert_eq!(trie.words(), vec!["Cafe\u{301}"]);
+
+        trie.delete("Café");
+        assert!(!trie.search("Cafe\u{301}"));
+        assert!(trie.words().is_empty());
+    }
+
+    #[test]
+    fn test_nfkc_normalization() {
+        let mut trie = RadixTrie::new().with_normalization(KeyNormalization::Nfkc);
+        trie.insert("ﬁle");
+
+        assert!(trie.search("file"));
+        assert_eq!(trie.words(), vec!["ﬁle"]);
+    }
+
+    #[test]
+    fn test_case_fold_normalization() {
+        let mut trie = RadixTrie::new().with_normalization(KeyNormalization::CaseFold);
+        trie.insert("Hello");
+        trie.insert("HELP");
+        trie.insert("world");
+
+        assert!(trie.search("hello"));
+        assert!(trie.search("HeLLo"));
+        assert!(trie.search("help"));
+        assert_eq!(trie.words(), vec!["Hello", "HELP", "world"]);
+
+        // Deleting a word keeps the original spelling of its neighbours after the nodes are compressed.
+        trie.delete("hel");
+        trie.delete("help");
+        assert_eq!(trie.words(), vec!["Hello", "world"]);
+    }
+
+    #[test]
+    fn test_keys_are_normalized() {
+        let mut trie = RadixTrie::new().with_normalization(KeyNormalization::CaseFold);
+        trie.insert("Help");
+        trie.insert("hello");
+        trie.insert("ﬁle");
+
+        assert_eq!(
+            trie.keys().collect::<Vec<_>>(),
+            vec!["file", "hello", "help"]
+        );
+        assert_eq!(trie.words(), vec!["ﬁle", "hello", "Help"]);
+    }
+
+    #[test]
+    fn test_no_normalization_by_default() {
+        let mut trie = RadixTrie::new();
+        trie.insert("Hello");
+        trie.insert("Cafe\u{301}");
+
+        assert!(!trie.search("hello"));
+        assert!(!trie.search("Café"));
+    }
+
+    fn edge_labels(trie: &RadixTrie) -> Vec<String> {
+        fn collect(arena: &LabelArena, node: &RadixTrieNode<()>, labels: &mut Vec<String>) {
+            for edge in &node.children {
+                labels.push(arena.get(&edge.label).to_string());
+                collect(arena, &edge.node, labels);
+            }
+        }
+        let mut labels = Vec::new();
+        collect(&trie.labels, &trie.root, &mut labels);
+        labels.sort();
+        labels
+    }
+
+    #[test]
+    fn test_grapheme_split_combining_marks() {
+        let mut trie = RadixTrie::new().with_split_mode(SplitMode::Grapheme);
+        trie.insert("cafe\u{301}");
+        trie.insert("cafe");
+        trie.insert("cafe\u{301}s");
+
+        assert_eq!(edge_labels(&trie), vec!["caf", "e", "e\u{301}", "s"]);
+        assert!(trie.search("cafe"));
+        assert!(trie.search("cafe\u{301}"));
+        assert!(trie.search("cafe\u{301}s"));
+        assert!(!trie.search("caf"));
+        assert!(!trie.search("cafes"));
+
+        trie.delete("cafe");
+        assert!(!trie.search("cafe"));
+        assert!(trie.search("cafe\u{301}"));
+        assert_eq!(edge_labels(&trie), vec!["cafe\u{301}", "s"]);
+    }
+
+    #[test]
+    fn test_grapheme_split_emoji_sequences() {
+        let mut trie = RadixTrie::new().with_split_mode(SplitMode::Grapheme);
+        trie.insert("dev 👩‍💻");
+        trie.insert("dev 👩‍🔬");
+        trie.insert("dev 👩");
+
+        assert_eq!(edge_labels(&trie), vec!["dev ", "👩", "👩‍💻", "👩‍🔬"]);
+        assert!(trie.search("dev 👩‍💻"));
+        assert!(trie.search("dev 👩‍🔬"));
+        assert!(trie.search("dev 👩"));
+        assert!(!trie.search("dev 👩\u{200d}"));
+
+        trie.delete("dev 👩‍🔬");
+        assert!(trie.search("dev 👩‍💻"));
+        assert!(!trie.search("dev 👩‍🔬"));
+    }
+
+    #[test]
+    fn test_char_split_cuts_emoji_sequences() {
+        let mut trie = RadixTrie::new();
+        trie.insert("👩‍💻");
+        trie.insert("👩‍🔬");
+
+        assert_eq!(edge_labels(&trie), vec!["👩\u{200d}", "💻", "🔬"]);
+    }
+
+    #[test]
+    fn test_long_labels_share_the_arena() {
+        let mut trie = RadixTrie::new();
+        trie.insert("internationalization");
+        trie.insert("internationalisation");
+        trie.insert("internal");
+
+        assert_eq!(
+            edge_labels(&trie),
+            vec!["interna", "l", "sation", "tionali", "zation"]
+        );
+        // Only the first word was too long to be inlined, splitting it reuses the same bytes.
+        assert_eq!(trie.labels.bytes.len(), "internationalization".len());
+
+        // Joining the split labels back together doesn't copy them either.
+        trie.delete("internal");
+        assert_eq!(
+            edge_labels(&trie),
+            vec!["internationali", "sation", "zation"]
+        );
+        assert_eq!(trie.labels.bytes.len(), "internationalization".len());
+        assert!(trie.search("internationalization"));
+        assert!(trie.search("internationalisation"));
+    }
+
+    #[test]
+    fn test_label_split_and_join() {
+        let mut arena = LabelArena::default();
+        let short = arena.alloc("hello");
+        let long = arena.alloc("a label that is too long to be inline");
+        assert!(matches!(short, Label::Inline { .. }));
+        assert!(matches!(long, Label::Arena { .. }));
+
+        let (head, tail) = short.split_at(2);
+        assert_eq!(arena.get(&head), "he");
+        assert_eq!(arena.get(&tail), "llo");
+
+        let (head, tail) = long.split_at(8);
+        assert_eq!(arena.get(&head), "a label ");
+        assert_eq!(arena.get(&tail), "that is too long to be inline");
+
+        let joined = arena.join(&short, &tail);
+        assert_eq!(arena.get(&joined), "hellothat is too long to be inline");
+    }
+
+    #[test]
+    fn test_cursor() {
+        let mut trie = RadixTrie::new();
+        trie.insert("hell");
+        trie.insert("hello");
+        trie.insert("help");
+        trie.insert("world");
+
+        let mut cursor = trie.cursor();
+        assert_eq!(cursor.next_chars().collect::<String>(), "hw");
+
+        for c in "hel".chars() {
+            assert!(cursor.feed_char(c));
+            assert!(!cursor.is_terminal());
+        }
+        assert_eq!(cursor.next_chars().collect::<String>(), "lp");
+
+        assert!(cursor.feed_char('l'));
+        assert!(cursor.is_terminal());
+        assert!(cursor.can_continue());
+        assert_eq!(cursor.next_chars().collect::<String>(), "o");
+
+        assert!(cursor.feed_char('o'));
+        assert!(cursor.is_terminal());
+        assert!(!cursor.can_continue());
+
+        assert!(!cursor.feed_char('!'));
+        assert!(!cursor.is_terminal());
+        assert!(!cursor.feed_char('o'));
+        assert_eq!(cursor.next_chars().count(), 0);
+    }
+
+    #[test]
+    fn test_cursor_values() {
+        let mut trie = RadixTrie::default();
+        trie.insert_value("hell", 1);
+        trie.insert_value("hello", 2);
+
+        let mut cursor = trie.cursor();
+        for c in "hel".chars() {
+            cursor.feed_char(c);
+        }
+        assert_eq!(cursor.value(), None);
+        cursor.feed_char('l');
+        assert_eq!(cursor.value(), Some(&1));
+        cursor.feed_char('o');
+        assert_eq!(cursor.value(), Some(&2));
+        cursor.feed_char('!');
+        assert_eq!(cursor.value(), None);
+    }
+
+    #[test]
+    fn test_cursor_bytes() {
+        let mut trie = RadixTrie::new();
+        trie.insert("café");
+        trie.insert("cafè");
+
+        let mut cursor = trie.cursor();
+        for byte in "caf".bytes() {
+            assert!(cursor.feed_byte(byte));
+        }
+        assert_eq!(cursor.next_chars().collect::<String>(), "èé");
+
+        // Both "é" and "è" start with the same byte.
+        let [first, second] = "é".as_bytes().try_into().unwrap();
+        assert!(cursor.feed_byte(first));
+        assert!(!cursor.is_terminal());
+        assert_eq!(cursor.next_chars().collect::<String>(), "èé");
+        assert!(cursor.feed_byte(second));
+        assert!(cursor.is_terminal());
+
+        let mut cursor = trie.cursor();
+        assert!(!cursor.feed_byte(0xff));
+        assert!(!cursor.can_continue());
+    }
+
+    #[test]
+    fn test_cursor_grapheme_siblings() {
+        let mut trie = RadixTrie::new().with_split_mode(SplitMode::Grapheme);
+        trie.insert("e");
+        trie.insert("e\u{301}t\u{e9}");
+        trie.insert("e\u{300}");
+
+        let mut cursor = trie.cursor();
+        assert!(cursor.feed_char('e'));
+        assert!(cursor.is_terminal());
+        assert_eq!(cursor.next_chars().collect::<String>(), "\u{300}\u{301}");
+
+        assert!(cursor.feed_char('\u{301}'));
+        assert!(!cursor.is_terminal());
+        assert!(cursor.feed_char('t'));
+        assert!(cursor.feed_char('\u{e9}'));
+        assert!(cursor.is_terminal());
+    }
+
+    #[test]
+    fn test_insert_and_delete_report_changes() {
+        let mut trie = RadixTrie::new();
+        assert!(trie.is_empty());
+
+        assert!(trie.insert("hello"));
+        assert!(trie.insert("hell"));
+        assert!(!trie.insert("hello"));
+        assert_eq!(trie.len(), 2);
+
+        assert!(!trie.delete("he"));
+        assert!(!trie.delete("helloo"));
+        assert_eq!(trie.len(), 2);
+
+        assert!(trie.delete("hell"));
+        assert!(!trie.delete("hell"));
+        assert_eq!(trie.len(), 1);
+
+        assert!(trie.delete("hello"));
+        assert!(trie.is_empty());
+        assert!(trie.words().is_empty());
+    }
+
+    #[test]
+    fn test_len_with_normalization() {
+        let mut trie = RadixTrie::new().with_normalization(KeyNormalization::CaseFold);
+        assert!(trie.insert("Hello"));
+        assert!(!trie.insert("hello"));
+        assert_eq!(trie.len(), 1);
+
+        assert!(trie.delete("HELLO"));
+        assert!(trie.is_empty());
+    }
+
+    #[test]
+    fn test_empty_word() {
+        let mut trie = RadixTrie::new();
+        assert!(!trie.search(""));
+        assert!(!trie.delete(""));
+
+        trie.insert("hello");
+        assert!(!trie.search(""));
+
+        assert!(trie.insert(""));
+        assert!(!trie.insert(""));
+        assert!(trie.search(""));
+        assert!(trie.search("hello"));
+        assert!(trie.cursor().is_terminal());
+        assert_eq!(trie.words(), vec!["", "hello"]);
+        assert_eq!(trie.len(), 2);
+
+        assert!(trie.delete(""));
+        assert!(!trie.search(""));
+        assert!(trie.search("hello"));
+        assert_eq!(trie.len(), 1);
+    }
+
+    #[test]
+    fn test_values() {
+        let mut trie = RadixTrie::default().with_normalization(KeyNormalization::CaseFold);
+        assert_eq!(trie.insert_value("Hello", 1), None);
+        assert_eq!(trie.insert_value("help", 2), None);
+        assert_eq!(trie.insert_value("HELLO", 3), Some(1));
+
+        assert_eq!(trie.get("hello"), Some(&3));
+        assert_eq!(trie.get("hel"), None);
+        assert_eq!(trie.words(), vec!["Hello", "help"]);
+
+        assert_eq!(trie.remove("HELP"), Some(2));
+        assert_eq!(trie.remove("help"), None);
+        assert_eq!(trie.len(), 1);
+    }
+
+    #[test]
+    fn test_top_k() {
+        let mut trie = RadixTrie::default().with_split_mode(SplitMode::Grapheme);
+        for (word, value) in [("e", 1), ("e\u{301}t\u{e9}", 5), ("ex", 3), ("x", 9)] {
+            trie.insert_value(word, value);
+        }
+
+        assert_eq!(
+            trie.top_k("e", 2),
+            vec![("e\u{301}t\u{e9}".to_string(), &5), ("ex".to_string(), &3)]
+        );
+        assert_eq!(trie.top_k("", 1), vec![("x".to_string(), &9)]);
+
+        trie.remove("x");
+        trie.insert_value("ex", 7);
+        assert_eq!(trie.top_k("", 1), vec![("ex".to_string(), &7)]);
+        assert!(trie.top_k("y", 1).is_empty());
+    }
+
+    #[test]
+    fn test_words_with_prefix() {
+        let trie = trie_from(&["hello", "hell", "help", "world", "he", "team"]);
+
+        assert_eq!(trie.words_with_prefix("hel"), vec!["hell", "hello", "help"]);
+        assert_eq!(trie.words_with_prefix("hell"), vec!["hell", "hello"]);
+        assert_eq!(trie.words_with_prefix("wo"), vec!["world"]);
+        assert_eq!(trie.words_with_prefix("world"), vec!["world"]);
+        assert_eq!(trie.words_with_prefix("").len(), 6);
+        assert!(trie.words_with_prefix("worlds").is_empty());
+        assert!(trie.words_with_prefix("x").is_empty());
+
+        let mut trie = RadixTrie::new().with_split_mode(SplitMode::Grapheme);
+        trie.insert("e");
+        trie.insert("e\u{301}t\u{e9}");
+        assert_eq!(trie.words_with_prefix("e").len(), 2);
+        assert_eq!(trie.words_with_prefix("e\u{301}"), vec!["e\u{301}t\u{e9}"]);
+    }
+
+    fn trie_from(words: &[&str]) -> RadixTrie {
+        let mut trie = RadixTrie::new();
+        for word in words {
+            trie.insert(word);
+        }
+        trie
+    }
+
+    #[test]
+    fn test_diff() {
+        let old = trie_from(&["hello", "hell", "world", "hi"]);
+        let new = trie_from(&["hello", "help", "world", "wow", ""]);
+
+        assert_eq!(
+            old.diff(&new).collect::<Vec<_>>(),
+            vec![
+                Change::Added("".to_string()),
+                Change::Removed("hell".to_string()),
+                Change::Added("help".to_string()),
+                Change::Removed("hi".to_string()),
+                Change::Added("wow".to_string()),
+            ]
+        );
+        assert_eq!(old.diff(&old).count(), 0);
+        assert_eq!(old.diff(&RadixTrie::new()).count(), 4);
+    }
+
+    #[test]
+    fn test_diff_across_edge_splits() {
+        // "hello" is a single edge in one trie, and split after "hell" in the other.
+        let old = trie_from(&["hello"]);
+        let new = trie_from(&["hell", "hello"]);
+        assert_eq!(
+            old.diff(&new).collect::<Vec<_>>(),
+            vec![Change::Added("hell".to_string())]
+        );
+        assert_eq!(
+            new.diff(&old).collect::<Vec<_>>(),
+            vec![Change::Removed("hell".to_string())]
+        );
+
+        let old = trie_from(&["testing", "team"]);
+        let new = trie_from(&["testing", "tester"]);
+        assert_eq!(
+            old.diff(&new).collect::<Vec<_>>(),
+            vec![
+                Change::Removed("team".to_string()),
+                Change::Added("tester".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_diff_after_changes() {
+        let mut old = trie_from(&["hello", "hell", "world"]);
+        let mut new = trie_from(&["hello", "hell", "world"]);
+        assert_eq!(old.diff(&new).count(), 0);
+
+        // The cached hashes have to be reset along the changed paths.
+        new.insert("help");
+        new.delete("world");
+        old.delete("hell");
+        assert_eq!(
+            old.diff(&new).collect::<Vec<_>>(),
+            vec![
+                Change::Added("hell".to_string()),
+                Change::Added("help".to_string()),
+                Change::Removed("world".to_string()),
+            ]
+        );
+
+        new.delete("help");
+        new.insert("world");
+        old.insert("hell");
+        assert_eq!(old.diff(&new).count(), 0);
+    }
+
+    #[test]
+    fn test_diff_grapheme_siblings() {
+        let mut old = RadixTrie::new().with_split_mode(SplitMode::Grapheme);
+        let mut new = RadixTrie::new().with_split_mode(SplitMode::Grapheme);
+        old.insert("e");
+        old.insert("e\u{301}");
+        new.insert("e\u{301}");
+        new.insert("ex");
+
+        assert_eq!(
+            old.diff(&new).collect::<Vec<_>>(),
+            vec![
+                Change::Removed("e".to_string()),
+                Change::Added("ex".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_diff_keeps_original_spelling() {
+        let mut old = RadixTrie::new().with_normalization(KeyNormalization::CaseFold);
+        let mut new = RadixTrie::new().with_normalization(KeyNormalization::CaseFold);
+        old.insert("Hello");
+        new.insert("HELLO");
+        new.insert("World");
+
+        assert_eq!(
+            old.diff(&new).collect::<Vec<_>>(),
+            vec![Change::Added("World".to_string())]
+        );
+    }
 }
@@ -0,0 +1,430 @@
+// Implementing an HTTP-style path router
+
+// Routes are stored as a tree of nodes. The static text following a node lives in a `RadixTrie` of its own, mapping
+// each run of static text up to the next parameter (or the end of the route) to the node it leads to. Named
+// parameters (`/users/:id`) and catch-alls (`/static/*path`) get their own slot on the node where their segment
+// starts. When matching, static text wins over parameters, which win over catch-alls, longer static text wins over
+// shorter, and the router backtracks if the preferred branch doesn't lead to a route.
+
+use super::radix::RadixTrie;
+use thiserror::Error;
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum RouteError {
+    #[error("Route {0} is already registered")]
+    Duplicate(String),
+
+    #[error(
+        "Route {route} names the parameter {new} but {existing} is already registered at the same position"
+    )]
+    ParamConflict {
+        route: String,
+        existing: String,
+        new: String,
+    },
+
+    #[error("Invalid route {route}: {reason}")]
+    Invalid { route: String, reason: &'static str },
+}
+
+// Nodes refer to each other by their index in the router.
+type NodeId = usize;
+
+const ROOT: NodeId = 0;
+
+#[derive(Debug, Clone)]
+struct RouterNode<T> {
+    // The static text that can follow this node, with the node each one leads to.
+    statics: RadixTrie<NodeId>,
+    param: Option<(String, NodeId)>,
+    catch_all: Option<(String, T)>,
+    value: Option<T>,
+}
+
+impl<T> Default for RouterNode<T> {
+    fn default() -> Self {
+        Self {
+            statics: RadixTrie::default(),
+            param: None,
+            catch_all: None,
+            value: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Router<T> {
+    nodes: Vec<RouterNode<T>>,
+}
+
+impl<T> Default for Router<T> {
+    fn default() -> Self {
+        Self {
+            nodes: vec![RouterNode::default()],
+        }
+    }
+}
+
+/// A successful match: the value registered for the route and the parameters captured from the path.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Match<'r, 'p, T> {
+    pub value: &'r T,
+    pub params: Params<'r, 'p>,
+}
+
+/// Parameters captured from a path, in the order they appear in the route.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Params<'r, 'p> {
+    entries: Vec<(&'r str, &'p str)>,
+}
+
+impl<'r, 'p> Params<'r, 'p> {
+    pub fn get(&self, name: &str) -> Option<&'p str> {
+        self.entries
+            .iter()
+            .find(|(key, _)| *key == name)
+            .map(|(_, value)| *value)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&'r str, &'p str)> + '_ {
+        self.entries.iter().copied()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+enum Piece<'a> {
+    Static(&'a str),
+    Param(&'a str),
+    CatchAll(&'a str),
+}
+
+impl<T> Router<T> {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Registers a route, failing if it conflicts with one that is already registered.
+    pub fn insert(&mut self, route: &str, value: T) -> Result<(), RouteError> {
+        let pieces = parse_route(route)?;
+
+        let mut current = ROOT;
+        for piece in pieces {
+            match piece {
+                Piece::Static(text) => {
+                    current = match self.nodes[current].statics.get(text) {
+                        Some(&next) => next,
+                        None => {
+                            let next = self.add_node();
+                            self.nodes[current].statics.insert_value(text, next);
+                            next
+                        }
+                    };
+                }
+                Piece::Param(name) => {
+                    current = match &self.nodes[current].param {
+                        Some((existing, next)) if existing == name => *next,
+                        Some((existing, _)) => {
+                            return Err(RouteError::ParamConflict {
+                                route: route.to_string(),
+                                existing: existing.clone(),
+                                new: name.to_string(),
+                            });
+                        }
+                        None => {
+                            let next = self.add_node();
+                            self.nodes[current].param = Some((name.to_string(), next));
+                            next
+                        }
+                    };
+                }
+                Piece::CatchAll(name) => {
+                    let node = &mut self.nodes[current];
+                    return match &node.catch_all {
+                        Some((existing, _)) if existing == name => {
+                            Err(RouteError::Duplicate(route.to_string()))
+                        }
+                        Some((existing, _)) => Err(RouteError::ParamConflict {
+                            route: route.to_string(),
+                            existing: existing.clone(),
+                            new: name.to_string(),
+                        }),
+                        None => {
+                            node.catch_all = Some((name.to_string(), value));
+                            Ok(())
+                        }
+                    };
+                }
+            }
+        }
+
+        let node = &mut self.nodes[current];
+        if node.value.is_some() {
+            return Err(RouteError::Duplicate(route.to_string()));
+        }
+        node.value = Some(value);
+        Ok(())
+    }
+
+    /// Finds the route matching a path, along with the parameters it captures.
+    pub fn at<'r, 'p>(&'r self, path: &'p str) -> Option<Match<'r, 'p, T>> {
+        let mut params = Params::default();
+        let value = self.match_node(ROOT, path, &mut params)?;
+        Some(Match { value, params })
+    }
+
+    fn add_node(&mut self) -> NodeId {
+        self.nodes.push(RouterNode::default());
+        self.nodes.len() - 1
+    }
+
+    fn match_node<'r, 'p>(
+        &'r self,
+        id: NodeId,
+        path: &'p str,
+        params: &mut Params<'r, 'p>,
+    ) -> Option<&'r T> {
+        let node = &self.nodes[id];
+        if path.is_empty()
+            && let Some(value) = &node.value
+        {
+            return Some(value);
+        }
+
+        // Every static text the path starts with, the longest is tried first.
+        let mut cursor = node.statics.cursor();
+        let mut matched = Vec::new();
+        for (index, c) in path.char_indices() {
+            if !cursor.feed_char(c) {
+                break;
+            }
+            if let Some(&next) = cursor.value() {
+                matched.push((index + c.len_utf8(), next));
+            }
+        }
+        for (len, next) in matched.into_iter().rev() {
+            if let Some(value) = self.match_node(next, &path[len..], params) {
+                return Some(value);
+            }
+        }
+
+        if let Some((name, next)) = &node.param {
+            let segment_len = path.find('/').unwrap_or(path.len());
+            if segment_len > 0 {
+                params.entries.push((name, &path[..segment_len]));
+                if let Some(value) = self.match_node(*next, &path[segment_len..], params) {
+                    return Some(value);
+                }
+                params.entries.pop();
+            }
+        }
+
+        if let Some((name, value)) = &node.catch_all
+            && !path.is_empty()
+        {
+            params.entries.push((name, path));
+            return Some(value);
+        }
+
+        None
+    }
+}
+
+// Splits a route into static text, parameters and catch-alls. Parameters and catch-alls span a whole segment, and
+// a catch-all can only be the last segment.
+fn parse_route(route: &str) -> Result<Vec<Piece<'_>>, RouteError> {
+    let invalid = |reason| RouteError::Invalid {
+        route: route.to_string(),
+        reason,
+    };
+    if !route.starts_with('/') {
+        return Err(invalid("routes must start with /"));
+    }
+
+    let mut pieces = Vec::new();
+    let mut static_start = 0;
+    let mut segment_start = 0;
+    while segment_start < route.len() {
+        let segment_end = route[segment_start + 1..]
+            .find('/')
+            .map_or(route.len(), |index| segment_start + 1 + index);
+        let segment = &route[segment_start + 1..segment_end];
+
+        let piece = match segment.chars().next() {
+            Some(':') => Piece::Param(&segment[1..]),
+            Some('*') if segment_end != route.len() => {
+                return Err(invalid("catch-all must be the last segment"));
+            }
+            Some('*') => Piece::CatchAll(&segment[1..]),
+            _ => {
+                if segment.contains([':', '*']) {
+                    return Err(invalid("parameters must span a whole segment"));
+                }
+                segment_start = segment_end;
+                continue;
+            }
+        };
+        if segment.len() == 1 {
+            return Err(invalid("parameters must be named"));
+        }
+
+        // The static text runs up to and including the slash before the parameter.
+        pieces.push(Piece::Static(&route[static_start..=segment_start]));
+        pieces.push(piece);
+        static_start = segment_end;
+        segment_start = segment_end;
+    }
+
+    if static_start < route.len() {
+        pieces.push(Piece::Static(&route[static_start..]));
+    }
+    Ok(pieces)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample_router() -> Router<&'static str> {
+        let mut router = Router::new();
+        for (route, value) in [
+            ("/", "index"),
+            ("/users", "list users"),
+            ("/users/:id", "get user"),
+            ("/users/new", "new user"),
+            ("/users/:id/posts/:post", "get post"),
+            ("/static/*path", "static"),
+            ("/search", "search"),
+        ] {
+            router.insert(route, value).unwrap();
+        }
+        router
+    }
+
+    #[test]
+    fn test_static_routes() {
+        let router = sample_router();
+
+        assert_eq!(router.at("/").unwrap().value, &"index");
+        assert_eq!(router.at("/users").unwrap().value, &"list users");
+        assert_eq!(router.at("/search").unwrap().value, &"search");
+        assert!(router.at("/use").is_none());
+        assert!(router.at("/users/").is_none());
+        assert!(router.at("").is_none());
+    }
+
+    #[test]
+    fn test_params() {
+        let router = sample_router();
+
+        let matched = router.at("/users/42").unwrap();
+        assert_eq!(matched.value, &"get user");
+        assert_eq!(matched.params.get("id"), Some("42"));
+
+        let matched = router.at("/users/42/posts/hello-world").unwrap();
+        assert_eq!(matched.value, &"get post");
+        assert_eq!(
+            matched.params.iter().collect::<Vec<_>>(),
+            vec![("id", "42"), ("post", "hello-world")]
+        );
+        assert!(router.at("/users/42/posts/").is_none());
+    }
+
+    #[test]
+    fn test_static_wins_over_param() {
+        let router = sample_router();
+
+        let matched = router.at("/users/new").unwrap();
+        assert_eq!(matched.value, &"new user");
+        assert!(matched.params.is_empty());
+
+        // Only the first segment is static, so the router backtracks to the parameter.
+        let matched = router.at("/users/newest").unwrap();
+        assert_eq!(matched.value, &"get user");
+        assert_eq!(matched.params.get("id"), Some("newest"));
+    }
+
+    #[test]
+    fn test_backtracks_to_shorter_static_text() {
+        let mut router = Router::new();
+        router.insert("/users/new/:step", "wizard").unwrap();
+        router.insert("/users/:id/", "user").unwrap();
+
+        // `/users/new/` is static text of its own, but the step after it is missing.
+        let matched = router.at("/users/new/").unwrap();
+        assert_eq!(matched.value, &"user");
+        assert_eq!(matched.params.get("id"), Some("new"));
+
+        let matched = router.at("/users/new/2").unwrap();
+        assert_eq!(matched.value, &"wizard");
+        assert_eq!(matched.params.get("step"), Some("2"));
+    }
+
+    #[test]
+    fn test_unicode_paths() {
+        let mut router = Router::new();
+        router.insert("/café/:item", "item").unwrap();
+        router.insert("/cafés", "list").unwrap();
+
+        assert_eq!(router.at("/cafés").unwrap().value, &"list");
+        let matched = router.at("/café/thé").unwrap();
+        assert_eq!(matched.params.get("item"), Some("thé"));
+        assert!(router.at("/caf").is_none());
+    }
+
+    #[test]
+    fn test_catch_all() {
+        let router = sample_router();
+
+        let matched = router.at("/static/css/site.css").unwrap();
+        assert_eq!(matched.value, &"static");
+        assert_eq!(matched.params.get("path"), Some("css/site.css"));
+        assert!(router.at("/static/").is_none());
+        assert!(router.at("/static").is_none());
+    }
+
+    #[test]
+    fn test_conflicts() {
+        let mut router = sample_router();
+
+        assert_eq!(
+            router.insert("/users/:id", "again"),
+            Err(RouteError::Duplicate("/users/:id".to_string()))
+        );
+        assert_eq!(
+            router.insert("/users/:name/settings", "settings"),
+            Err(RouteError::ParamConflict {
+                route: "/users/:name/settings".to_string(),
+                existing: "id".to_string(),
+                new: "name".to_string(),
+            })
+        );
+        assert!(matches!(
+            router.insert("/static/*file", "files"),
+            Err(RouteError::ParamConflict { .. })
+        ));
+        assert!(matches!(
+            router.insert("/static/*path", "files"),
+            Err(RouteError::Duplicate(_))
+        ));
+        assert!(router.insert("/users/:id/settings", "settings").is_ok());
+    }
+
+    #[test]
+    fn test_invalid_routes() {
+        let mut router = Router::new();
+
+        for route in ["users", "/files/*path/edit", "/users/:", "/users/id:x"] {
+            assert!(
+                matches!(router.insert(route, ()), Err(RouteError::Invalid { .. })),
+                "{route} should be invalid"
+            );
+        }
+    }
+}